@@ -1,32 +1,44 @@
 /* src/acme.rs */
 
 use crate::{
-    config::{self, AppConfig, add_domain_to_config},
+    config::{AppConfig, CertificateEntry, add_certificate_to_config},
+    jobs::{JobLog, JobStatus},
+    lego,
+    metadata::{self, CertificateMetadata},
+    redact,
+    renewal::ScheduledRenewal,
     state::{AppState, DomainStatus},
 };
 use chrono::{DateTime, Utc};
 use fancy_log::{LogLevel, log};
-use fancy_regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::{
     fs,
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::Command,
+    process::{Child, Command},
+    sync::Notify,
+    time,
 };
 use x509_parser::prelude::*;
 
-pub async fn certificate_exists(domain: &str, config: &AppConfig) -> bool {
-    let domain_name = domain.trim();
-    let cert_dir = config.dir_path.join(".lego/certificates");
-    let key_path_exact = cert_dir.join(format!("{}.key", domain_name));
-    let key_path_wildcard = cert_dir.join(format!("_.{}.key", domain_name));
-    if tokio::fs::metadata(key_path_wildcard).await.is_ok() {
-        return true;
-    }
-    if tokio::fs::metadata(key_path_exact).await.is_ok() {
-        return true;
-    }
-    false
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Looks up the lego resource for `entry` and records it in the state.
+/// Returns whether a certificate and key exist for it.
+pub async fn certificate_exists(app_state: &AppState, entry: &CertificateEntry) -> bool {
+    let cert_dir = app_state.config.dir_path.join(".lego/certificates");
+    let found = metadata::find(entry, &cert_dir).await;
+    let exists = found.is_some();
+    let mut all = app_state.metadata.write();
+    match found {
+        Some(resource) => all.insert(entry.id.clone(), resource),
+        None => all.remove(&entry.id),
+    };
+    exists
 }
 
 #[derive(Clone, Copy)]
@@ -35,22 +47,35 @@ pub enum CommandType {
     Renew,
 }
 
+/// Runs job `job_id`, which is run number `attempt` for `entry`. Returns when
+/// to retry if it failed and the entry's retry policy allows another run.
 pub async fn acquire_or_renew_certificate(
     app_state: AppState,
-    domain: String,
-    dns_provider: String,
+    entry: CertificateEntry,
     persist: bool,
     command_type: CommandType,
-) {
+    job_id: String,
+    attempt: u32,
+) -> Option<DateTime<Utc>> {
     let config = app_state.config.clone();
-    let domain_name = domain.trim();
+    let domain_name = entry.id.as_str();
 
     app_state
         .domains
         .write()
-        .insert(domain_name.to_string(), DomainStatus::Acquiring);
+        .set(domain_name, DomainStatus::Acquiring);
 
-    let result = do_execute_lego(domain_name, &dns_provider, &config, command_type).await;
+    let result = do_execute_lego(&app_state, &entry, command_type, &job_id).await;
+    let job_status = match &result {
+        Ok(_) => JobStatus::Succeeded,
+        Err(e) if matches!(e.downcast_ref(), Some(RunAborted::Cancelled)) => JobStatus::Cancelled,
+        Err(_) => JobStatus::Failed,
+    };
+    app_state.jobs.write().finish(
+        &job_id,
+        job_status,
+        result.as_ref().err().map(ToString::to_string),
+    );
 
     match result {
         Ok(_) => {
@@ -62,213 +87,350 @@ pub async fn acquire_or_renew_certificate(
                 LogLevel::Info,
                 &format!("{} '{}'", success_msg, domain_name),
             );
+            if !certificate_exists(&app_state, &entry).await {
+                log(
+                    LogLevel::Warn,
+                    &format!(
+                        "lego reported success, but no matching resource file was found for '{}'",
+                        domain_name
+                    ),
+                );
+            }
             app_state
                 .domains
                 .write()
-                .insert(domain_name.to_string(), DomainStatus::Ready);
+                .set(domain_name, DomainStatus::Ready);
             if persist {
                 let config_path = config.dir_path.join("config.toml");
-                if let Err(e) = add_domain_to_config(&config_path, domain_name, &dns_provider).await
-                {
+                if let Err(e) = add_certificate_to_config(&config_path, &entry).await {
                     log(
                         LogLevel::Error,
-                        &format!("Failed to persist domain to config.toml: {}", e),
+                        &format!("Failed to persist certificate to config.toml: {}", e),
                     );
                 }
             }
+            None
         }
         Err(e) => {
             let err_msg = e.to_string();
+            // A cancelled job stays cancelled.
+            let next_retry_at = match job_status {
+                JobStatus::Failed => entry.retry_policy(&config).next_attempt_at(attempt),
+                _ => None,
+            };
+            let retry_note = match next_retry_at {
+                Some(at) => format!(" (attempt {}, retrying at {})", attempt, at),
+                None => format!(" (attempt {}, not retrying)", attempt),
+            };
             log(
                 LogLevel::Error,
                 &format!(
-                    "Failed to acquire/renew certificate for '{}': {}",
-                    domain_name, err_msg
+                    "Failed to acquire/renew certificate for '{}': {}{}",
+                    domain_name, err_msg, retry_note
                 ),
             );
-            app_state
-                .domains
-                .write()
-                .insert(domain_name.to_string(), DomainStatus::Failed(err_msg));
+            app_state.domains.write().set(
+                domain_name,
+                DomainStatus::Failed {
+                    reason: err_msg,
+                    attempts: attempt,
+                    next_retry_at,
+                },
+            );
+            next_retry_at
         }
     }
-
-    *app_state.is_acquiring.write() = false;
-    log(LogLevel::Debug, "Global acquisition lock released.");
-}
-
-fn sanitize_command_for_log(command: &str) -> String {
-    let re = Regex::new(r#"(?i)([^=\s]+)=(['"]?)[^'"\s]+\2(?=\s+lego)"#).unwrap();
-    re.replace_all(command, "$1=***").to_string()
 }
 
+/// Runs lego for `entry`, capturing its output into the job's log.
+/// Errors are returned with secret values already scrubbed.
 async fn do_execute_lego(
-    domain: &str,
-    dns_provider: &str,
-    config: &AppConfig,
+    app_state: &AppState,
+    entry: &CertificateEntry,
     command_type: CommandType,
+    job_id: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let provider_config_path = config
-        .dir_path
-        .join(format!("{}.dns.toml", dns_provider.trim()));
-
-    if !provider_config_path.exists() {
-        return Err(format!(
-            "DNS provider config not found at {:?}",
-            provider_config_path
-        )
-        .into());
-    }
-
-    let provider_config = config::load_dns_provider_config(&provider_config_path).await?;
-    let placeholder_re = ::regex::Regex::new(r"\{\{([a-zA-Z0-9_]+)\}\}")?;
-
-    let command_template = match command_type {
-        CommandType::Run => provider_config.cmd.clone(),
-        CommandType::Renew => provider_config
-            .renew
-            .clone()
-            .unwrap_or_else(|| provider_config.cmd.clone()),
+    let config = &app_state.config;
+    let cancel = app_state
+        .jobs
+        .read()
+        .cancel_signal(job_id)
+        .unwrap_or_default();
+    let started = time::Instant::now();
+    // Resolving secrets and looking up zones can stall too. The provider's
+    // own timeout is only known once its config is read, so building the
+    // invocation is bounded by the default one.
+    let built = tokio::select! {
+        result = lego::build_invocation(entry, config, command_type) => result,
+        aborted = aborted(&cancel, started, lego::DEFAULT_TIMEOUT) => {
+            log(LogLevel::Warn, &format!("{} while preparing it.", aborted));
+            Err(aborted.into())
+        }
+    };
+    let invocation = match built {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            let job_log = JobLog::new(app_state.jobs.clone(), job_id, Vec::new());
+            job_log.append(&e.to_string());
+            return Err(e);
+        }
     };
 
-    let mut final_cmd = command_template;
-    for cap in placeholder_re.captures_iter(&final_cmd.clone()) {
-        let placeholder = &cap[0];
-        let key = &cap[1];
-        let value = if key.eq_ignore_ascii_case("DOMAIN") {
-            domain.to_string()
+    let job_log = JobLog::new(app_state.jobs.clone(), job_id, invocation.secrets.clone());
+    let display = invocation.display();
+    log(LogLevel::Debug, &format!("Executing command: {}", display));
+    job_log.append(&format!("$ {}", display));
+
+    execute_lego_command(
+        invocation.command(),
+        &config.dir_path,
+        &job_log,
+        aborted(&cancel, started, invocation.timeout),
+    )
+    .await
+    .map_err(|e| {
+        let message = job_log.redact(&e.to_string());
+        job_log.append(&message);
+        if e.is::<RunAborted>() {
+            e
         } else {
-            provider_config
-                .vars
-                .iter()
-                .find(|(k, _)| k.eq_ignore_ascii_case(key))
-                .and_then(|(_, v)| v.as_str().map(ToString::to_string))
-                .unwrap_or_default()
-        };
-        final_cmd = final_cmd.replace(placeholder, &value);
+            message.into()
+        }
+    })
+}
+
+/// Resolves when the job is cancelled or `timeout` has passed since it
+/// `started`.
+async fn aborted(cancel: &Notify, started: time::Instant, timeout: Duration) -> RunAborted {
+    tokio::select! {
+        _ = time::sleep_until(started + timeout) => RunAborted::TimedOut(timeout),
+        _ = cancel.notified() => RunAborted::Cancelled,
     }
+}
 
-    let sanitized_cmd = sanitize_command_for_log(&final_cmd);
-    log(
-        LogLevel::Debug,
-        &format!("Executing command: {}", sanitized_cmd),
-    );
+/// Why a lego run was stopped before it exited on its own.
+#[derive(Debug)]
+pub enum RunAborted {
+    TimedOut(Duration),
+    Cancelled,
+}
 
-    execute_lego_command(&final_cmd, &config.dir_path).await
+impl fmt::Display for RunAborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunAborted::TimedOut(timeout) => {
+                write!(f, "Lego command timed out after {}s", timeout.as_secs())
+            }
+            RunAborted::Cancelled => write!(f, "Lego command was cancelled"),
+        }
+    }
 }
 
+impl std::error::Error for RunAborted {}
+
 async fn execute_lego_command(
-    command: &str,
+    mut cmd: Command,
     working_dir: &Path,
+    job_log: &JobLog,
+    aborted: impl Future<Output = RunAborted>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut cmd = Command::new("sh");
-    cmd.arg("-c")
-        .arg(command)
-        .current_dir(working_dir)
+    cmd.current_dir(working_dir)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped());
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    // lego gets its own process group, so DNS hooks it spawns can be killed with it.
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("Failed to start {:?}: {}", cmd.as_std().get_program(), e))?;
+
+    let aborted = tokio::select! {
+        result = drain_and_wait(&mut child, job_log) => return result,
+        aborted = aborted => aborted,
+    };
 
-    let mut child = cmd.spawn()?;
+    log(
+        LogLevel::Warn,
+        &format!("{}, stopping its process group.", aborted),
+    );
+    kill_process_group(&mut child).await;
+    Err(aborted.into())
+}
+
+async fn drain_and_wait(
+    child: &mut Child,
+    job_log: &JobLog,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut stdin = child.stdin.take().expect("Failed to open stdin");
     let stdout = child.stdout.take().expect("Failed to open stdout");
     let stderr = child.stderr.take().expect("Failed to open stderr");
     let mut stdout_reader = BufReader::new(stdout).lines();
     let mut stderr_reader = BufReader::new(stderr).lines();
 
-    loop {
+    // Drain both streams until lego closes them, then collect the exit status.
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+    while !(stdout_done && stderr_done) {
         tokio::select! {
-            result = stdout_reader.next_line() => {
+            result = stdout_reader.next_line(), if !stdout_done => {
                 match result {
                     Ok(Some(line)) => {
-                        log(LogLevel::Info, &line);
+                        job_log.append(&line);
+                        log(LogLevel::Info, &job_log.redact(&line));
                         if line.contains("Do you accept the TOS? Y/n") {
                             log(LogLevel::Warn, "TOS prompt detected. Responding with 'y'.");
                             stdin.write_all(b"y\n").await?;
                         }
                     },
-                    Ok(None) => break,
-                    Err(e) => log(LogLevel::Error, &e.to_string()),
+                    Ok(None) => stdout_done = true,
+                    Err(e) => {
+                        log(LogLevel::Error, &e.to_string());
+                        stdout_done = true;
+                    },
                 }
             },
-            result = stderr_reader.next_line() => {
-                  match result {
-                    Ok(Some(line)) => log(LogLevel::Error, &line),
-                    Ok(None) => {},
-                    Err(e) => log(LogLevel::Error, &e.to_string()),
+            result = stderr_reader.next_line(), if !stderr_done => {
+                match result {
+                    Ok(Some(line)) => {
+                        job_log.append(&line);
+                        log(LogLevel::Error, &job_log.redact(&line));
+                    },
+                    Ok(None) => stderr_done = true,
+                    Err(e) => {
+                        log(LogLevel::Error, &e.to_string());
+                        stderr_done = true;
+                    },
                 }
             },
-            status = child.wait() => {
-                let exit_status = status?;
-                if exit_status.success() {
-                    log(LogLevel::Info, "Lego command finished successfully.");
-                } else {
-                    let err_msg = format!("Lego command failed with status: {}", exit_status);
-                    log(LogLevel::Error, &err_msg);
-                    return Err(err_msg.into());
-                }
+        }
+    }
+
+    let exit_status = child.wait().await?;
+    if exit_status.success() {
+        log(LogLevel::Info, "Lego command finished successfully.");
+        Ok(())
+    } else {
+        let err_msg = format!("Lego command failed with status: {}", exit_status);
+        log(LogLevel::Error, &err_msg);
+        Err(err_msg.into())
+    }
+}
+
+/// Sends SIGTERM to the child's process group, then SIGKILL after a grace period.
+async fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let pgid = pid as libc::pid_t;
+        // SAFETY: plain syscalls on a process group we created for this child.
+        unsafe {
+            libc::killpg(pgid, libc::SIGTERM);
+        }
+        let exited = time::timeout(KILL_GRACE_PERIOD, child.wait()).await.is_ok();
+        // Until the leader is reaped, or while other members remain, the
+        // group id cannot be handed to anyone else. Once the group is empty
+        // it may be, so it is left alone.
+        unsafe {
+            if !exited || libc::killpg(pgid, 0) == 0 {
+                libc::killpg(pgid, libc::SIGKILL);
             }
         }
     }
-    Ok(())
+    let _ = child.kill().await;
+}
+
+/// What a run for `entry` would do, with secrets redacted. Nothing is
+/// executed and no state is changed.
+#[derive(Serialize)]
+pub struct DryRunPlan {
+    pub id: String,
+    pub sans: Vec<String>,
+    pub action: &'static str,
+    pub command: String,
+    pub env: BTreeMap<String, String>,
+    pub timeout_secs: u64,
+    pub working_dir: PathBuf,
+    /// Files lego would write, named after the first SAN.
+    pub certificate_files: Vec<PathBuf>,
+    pub existing_certificate: Option<CertificateMetadata>,
 }
 
-pub async fn needs_renewal(
-    domain: &str,
+/// Plans a renewal when a certificate exists, otherwise a first issuance,
+/// and runs the same checks a real run would.
+pub async fn plan(
     config: &AppConfig,
-    days_before_expiry: i64,
-) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    let domain_name = domain.trim();
+    entry: &CertificateEntry,
+) -> Result<DryRunPlan, Box<dyn std::error::Error + Send + Sync>> {
+    if !fs::metadata(&config.dir_path)
+        .await
+        .is_ok_and(|m| m.is_dir())
+    {
+        return Err(format!("Data directory {:?} does not exist", config.dir_path).into());
+    }
+    let cert_dir = config.dir_path.join(".lego/certificates");
+    if fs::metadata(&cert_dir).await.is_ok_and(|m| !m.is_dir()) {
+        return Err(format!("{:?} is not a directory", cert_dir).into());
+    }
+
+    lego::check_routing(entry, config).await?;
+    let existing_certificate = metadata::find(entry, &cert_dir).await;
+    let command_type = match existing_certificate {
+        Some(_) => CommandType::Renew,
+        None => CommandType::Run,
+    };
+    let invocation = lego::build_invocation(entry, config, command_type).await?;
+    let stem = entry.file_stem();
+    Ok(DryRunPlan {
+        id: entry.id.clone(),
+        sans: entry.sans.clone(),
+        action: match command_type {
+            CommandType::Run => "run",
+            CommandType::Renew => "renew",
+        },
+        command: invocation.display(),
+        env: invocation
+            .env
+            .iter()
+            .map(|(k, v)| (k.clone(), redact::redact(v, &invocation.secrets)))
+            .collect(),
+        timeout_secs: invocation.timeout.as_secs(),
+        working_dir: config.dir_path.clone(),
+        certificate_files: ["crt", "key", "json"]
+            .iter()
+            .map(|ext| cert_dir.join(format!("{}.{}", stem, ext)))
+            .collect(),
+        existing_certificate,
+    })
+}
+
+/// When the issued certificate for `entry` expires and is due for renewal.
+pub async fn scheduled_renewal(
+    entry: &CertificateEntry,
+    config: &AppConfig,
+) -> Result<ScheduledRenewal, Box<dyn std::error::Error + Send + Sync>> {
     let cert_dir = config.dir_path.join(".lego/certificates");
 
-    let cert_path = find_cert_file(domain_name, &cert_dir)
+    let cert_path = metadata::find(entry, &cert_dir)
         .await
-        .ok_or("Certificate file not found for renewal check.")?;
+        .ok_or("Certificate file not found for renewal check.")?
+        .path(&cert_dir, "crt");
 
     let cert_data = fs::read(&cert_path).await?;
     let pem = ::pem::parse(&cert_data)?;
     let (_, x509_cert) = X509Certificate::from_der(pem.contents())?;
 
-    let not_after_str = x509_cert
-        .validity()
-        .not_after
-        .to_rfc2822()
-        .map_err(|e| e.to_string())?;
-    let expiry_date = DateTime::parse_from_rfc2822(&not_after_str)?.with_timezone(&Utc);
-
-    let now = Utc::now();
-    let threshold = chrono::Duration::days(days_before_expiry);
-
-    let needs_renew = expiry_date - now < threshold;
-    if needs_renew {
-        log(
-            LogLevel::Warn,
-            &format!(
-                "Certificate for '{}' expires on {} (in less than {} days). Renewal required.",
-                domain, expiry_date, days_before_expiry
-            ),
-        );
-    } else {
-        log(
-            LogLevel::Info,
-            &format!(
-                "Certificate for '{}' is valid until {}. No renewal needed.",
-                domain, expiry_date
-            ),
-        );
-    }
-
-    Ok(needs_renew)
-}
-
-async fn find_cert_file(domain: &str, cert_dir: &Path) -> Option<PathBuf> {
-    let wildcard_path = cert_dir.join(format!("_.{}.crt", domain));
-    if tokio::fs::metadata(&wildcard_path).await.is_ok() {
-        return Some(wildcard_path);
-    }
-    let exact_path = cert_dir.join(format!("{}.crt", domain));
-    if tokio::fs::metadata(&exact_path).await.is_ok() {
-        return Some(exact_path);
-    }
-    None
+    let validity = x509_cert.validity();
+    let parse = |time: &x509_parser::time::ASN1Time| {
+        let rfc2822 = time.to_rfc2822().map_err(|e| e.to_string())?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+            DateTime::parse_from_rfc2822(&rfc2822)?.with_timezone(&Utc),
+        )
+    };
+    Ok(ScheduledRenewal::new(
+        parse(&validity.not_before)?,
+        parse(&validity.not_after)?,
+        entry.renew_before(config),
+    ))
 }
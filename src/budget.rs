@@ -0,0 +1,484 @@
+/* src/budget.rs */
+
+use crate::config::{self, AppConfig, CertificateEntry, RateLimits};
+use crate::history::{self, ErrorClass};
+use crate::jobs::{JobRecord, JobStatus};
+use crate::lego;
+use crate::persist::StateFile;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+pub const RATE_LIMITS_FILE: &str = "ratelimits.json";
+
+/// What a job counts against: the providers answering its challenges, the
+/// ACME directory and account it orders from, and the names it covers.
+#[derive(Clone, Debug)]
+pub struct JobProfile {
+    /// Each provider with the number of jobs it may run at once.
+    pub providers: Vec<(String, usize)>,
+    pub ca: String,
+    pub account: String,
+    pub hostnames: Vec<String>,
+    pub registered_domains: Vec<String>,
+}
+
+impl JobProfile {
+    /// Reads the caps, CA and account from the entry's provider configs. A
+    /// config that does not load falls back to the defaults; the job itself
+    /// reports that error once it runs.
+    pub async fn load(config: &AppConfig, entry: &CertificateEntry) -> Self {
+        let mut providers = Vec::new();
+        let mut ca = None;
+        let mut account = String::new();
+        for provider in entry.providers() {
+            let path = config.dir_path.join(format!("{}.dns.toml", provider));
+            let provider_config = config::load_dns_provider_config(&path).await.ok();
+            let cap = provider_config
+                .as_ref()
+                .and_then(|c| c.max_concurrent)
+                .unwrap_or(config.max_jobs_per_provider)
+                .max(1);
+            // lego takes the CA and account from the default provider.
+            if provider == entry.dns_provider {
+                ca = provider_config.as_ref().map(lego::ca_url);
+                account = provider_config
+                    .as_ref()
+                    .and_then(|c| c.var_str("email"))
+                    .unwrap_or_default();
+            }
+            providers.push((provider.to_string(), cap));
+        }
+
+        let mut hostnames: Vec<String> = Vec::new();
+        let mut registered_domains: Vec<String> = Vec::new();
+        for san in &entry.sans {
+            let hostname = san.trim_start_matches("*.").to_string();
+            let registered = if hostname.parse::<IpAddr>().is_ok() {
+                hostname.clone()
+            } else {
+                psl::domain_str(&hostname).unwrap_or(&hostname).to_string()
+            };
+            if !registered_domains.contains(&registered) {
+                registered_domains.push(registered);
+            }
+            if !hostnames.contains(&hostname) {
+                hostnames.push(hostname);
+            }
+        }
+
+        JobProfile {
+            providers,
+            ca: ca.unwrap_or_else(|| lego::DEFAULT_CA.to_string()),
+            account,
+            hostnames,
+            registered_domains,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Order,
+    Issued,
+    FailedValidation,
+}
+
+/// One order, issuance or failure counted against a CA limit. `key` is the
+/// account for orders, the registered domain for issuances and the account
+/// and hostname for failures.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RateEvent {
+    pub kind: EventKind,
+    pub ca: String,
+    pub key: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Why a job cannot start yet.
+pub enum Blocked {
+    /// A concurrency cap; it frees up when a running job finishes.
+    Busy(String),
+    /// A CA rate limit; it frees up at the given time.
+    RateLimited(String, DateTime<Utc>),
+}
+
+/// Concurrency slots in use and the recent CA events, the latter mirrored
+/// to `ratelimits.json` so a restart does not forget them.
+pub struct Budget {
+    limits: RateLimits,
+    max_per_ca: usize,
+    running_providers: HashMap<String, usize>,
+    running_cas: HashMap<String, usize>,
+    events: Vec<RateEvent>,
+    file: StateFile,
+}
+
+impl Budget {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            limits: config.rate_limits,
+            max_per_ca: config.max_jobs_per_ca,
+            running_providers: HashMap::new(),
+            running_cas: HashMap::new(),
+            events: Vec::new(),
+            file: state_file(&config.dir_path),
+        }
+    }
+
+    pub fn restore(&mut self, mut events: Vec<RateEvent>) {
+        events.sort_by_key(|e| e.at);
+        self.events = events;
+        self.prune(Utc::now());
+    }
+
+    /// Checks whether a job with `profile` may start now.
+    pub fn check(&self, profile: &JobProfile, now: DateTime<Utc>) -> Result<(), Blocked> {
+        for (provider, cap) in &profile.providers {
+            if self.running_providers.get(provider).copied().unwrap_or(0) >= *cap {
+                return Err(Blocked::Busy(format!(
+                    "provider '{}' is running its limit of {} job(s)",
+                    provider, cap
+                )));
+            }
+        }
+        if self.running_cas.get(&profile.ca).copied().unwrap_or(0) >= self.max_per_ca {
+            return Err(Blocked::Busy(format!(
+                "CA {} is running its limit of {} job(s)",
+                profile.ca, self.max_per_ca
+            )));
+        }
+
+        let mut blocked: Option<(String, DateTime<Utc>)> = None;
+        let mut limit = |kind, key: &str, max: usize, window: Duration, what: String| {
+            if let Some(free_at) = self.free_at(kind, &profile.ca, key, max, window, now)
+                && blocked.as_ref().is_none_or(|(_, at)| free_at > *at)
+            {
+                blocked = Some((what, free_at));
+            }
+        };
+        limit(
+            EventKind::Order,
+            &profile.account,
+            self.limits.orders_per_3h,
+            Duration::hours(3),
+            format!(
+                "account '{}' reached {} new orders per 3 hours",
+                profile.account, self.limits.orders_per_3h
+            ),
+        );
+        for domain in &profile.registered_domains {
+            limit(
+                EventKind::Issued,
+                domain,
+                self.limits.certs_per_domain_per_week,
+                Duration::weeks(1),
+                format!(
+                    "'{}' reached {} certificates per week",
+                    domain, self.limits.certs_per_domain_per_week
+                ),
+            );
+        }
+        for hostname in &profile.hostnames {
+            limit(
+                EventKind::FailedValidation,
+                &format!("{} {}", profile.account, hostname),
+                self.limits.failed_validations_per_hour,
+                Duration::hours(1),
+                format!(
+                    "'{}' reached {} failed validations per hour",
+                    hostname, self.limits.failed_validations_per_hour
+                ),
+            );
+        }
+        match blocked {
+            Some((reason, at)) => Err(Blocked::RateLimited(reason, at)),
+            None => Ok(()),
+        }
+    }
+
+    /// When another event of `kind` fits under `max` again, or `None` if it
+    /// fits now.
+    fn free_at(
+        &self,
+        kind: EventKind,
+        ca: &str,
+        key: &str,
+        max: usize,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if max == 0 {
+            return None;
+        }
+        let recent: Vec<DateTime<Utc>> = self
+            .events
+            .iter()
+            .filter(|e| e.kind == kind && e.ca == ca && e.key == key && e.at > now - window)
+            .map(|e| e.at)
+            .collect();
+        (recent.len() >= max).then(|| recent[recent.len() - max] + window)
+    }
+
+    /// Takes the job's slots.
+    pub fn start(&mut self, profile: &JobProfile) {
+        for (provider, _) in &profile.providers {
+            *self.running_providers.entry(provider.clone()).or_default() += 1;
+        }
+        *self.running_cas.entry(profile.ca.clone()).or_default() += 1;
+    }
+
+    /// Frees the job's slots. If lego ran, counts its order and its outcome:
+    /// an issued certificate per registered domain, or a failed validation
+    /// per hostname. Lego does not report which name failed, so a failed job
+    /// counts against all of them. Jobs that never reached the CA, or failed
+    /// for a reason other than validation, count for nothing.
+    pub fn finish(&mut self, profile: &JobProfile, job: Option<&JobRecord>) {
+        for (provider, _) in &profile.providers {
+            if let Some(count) = self.running_providers.get_mut(provider) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        if let Some(count) = self.running_cas.get_mut(&profile.ca) {
+            *count = count.saturating_sub(1);
+        }
+        let Some(job) = job.filter(|job| history::lego_ran(job.error.as_deref(), &job.log)) else {
+            return;
+        };
+        self.record(EventKind::Order, profile, profile.account.clone());
+        match job.status {
+            JobStatus::Succeeded => {
+                for domain in &profile.registered_domains {
+                    self.record(EventKind::Issued, profile, domain.clone());
+                }
+            }
+            JobStatus::Failed => {
+                let class =
+                    ErrorClass::classify(job.error.as_deref().unwrap_or_default(), &job.log);
+                if matches!(
+                    class,
+                    ErrorClass::Dns | ErrorClass::Authorization | ErrorClass::Lego
+                ) {
+                    for hostname in &profile.hostnames {
+                        let key = format!("{} {}", profile.account, hostname);
+                        self.record(EventKind::FailedValidation, profile, key);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.save();
+    }
+
+    fn record(&mut self, kind: EventKind, profile: &JobProfile, key: String) {
+        let now = Utc::now();
+        self.prune(now);
+        self.events.push(RateEvent {
+            kind,
+            ca: profile.ca.clone(),
+            key,
+            at: now,
+        });
+    }
+
+    /// Drops events older than the longest window.
+    fn prune(&mut self, now: DateTime<Utc>) {
+        self.events.retain(|e| e.at > now - Duration::weeks(1));
+    }
+
+    fn save(&self) {
+        self.file.save(&self.events);
+    }
+}
+
+fn state_file(dir_path: &Path) -> StateFile {
+    StateFile::new(dir_path.join(RATE_LIMITS_FILE), "rate limit events")
+}
+
+/// Reads the events a previous run left in `ratelimits.json`.
+pub async fn load(dir_path: &Path) -> Vec<RateEvent> {
+    state_file(dir_path).load().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobKind, JobStore, Trigger};
+
+    const CA: &str = "https://ca.example/directory";
+
+    fn test_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "lazy-acme-test-{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn budget(name: &str, limits: RateLimits) -> Budget {
+        let path = test_path(name);
+        Budget {
+            limits,
+            max_per_ca: 2,
+            running_providers: HashMap::new(),
+            running_cas: HashMap::new(),
+            events: Vec::new(),
+            file: StateFile::new(path, "rate limit events"),
+        }
+    }
+
+    fn limits(orders: usize, certs: usize, failed: usize) -> RateLimits {
+        RateLimits {
+            orders_per_3h: orders,
+            certs_per_domain_per_week: certs,
+            failed_validations_per_hour: failed,
+        }
+    }
+
+    fn profile() -> JobProfile {
+        JobProfile {
+            providers: vec![("cloudflare".to_string(), 1)],
+            ca: CA.to_string(),
+            account: "me@example.com".to_string(),
+            hostnames: vec!["example.com".to_string(), "www.example.org".to_string()],
+            registered_domains: vec!["example.com".to_string(), "example.org".to_string()],
+        }
+    }
+
+    fn event(kind: EventKind, ca: &str, key: &str, at: DateTime<Utc>) -> RateEvent {
+        RateEvent {
+            kind,
+            ca: ca.to_string(),
+            key: key.to_string(),
+            at,
+        }
+    }
+
+    fn finished(status: JobStatus, error: Option<&str>, log: &[&str]) -> JobRecord {
+        let mut jobs = JobStore::default();
+        let id = jobs.create("web", JobKind::Acquire, Trigger::Api);
+        let mut job = jobs.get(&id).unwrap().clone();
+        job.status = status;
+        job.error = error.map(str::to_string);
+        job.log = log.iter().map(|line| line.to_string()).collect();
+        job
+    }
+
+    fn count(budget: &Budget, kind: EventKind) -> usize {
+        budget.events.iter().filter(|e| e.kind == kind).count()
+    }
+
+    #[test]
+    fn free_at_is_when_the_oldest_counted_event_leaves_the_window() {
+        let now = Utc::now();
+        let mut budget = budget("free-at", limits(0, 0, 0));
+        let window = Duration::hours(1);
+        let free_at =
+            |budget: &Budget, max| budget.free_at(EventKind::Order, CA, "a", max, window, now);
+
+        budget.events = vec![
+            event(EventKind::Order, CA, "a", now - Duration::hours(2)),
+            event(EventKind::Order, CA, "a", now - Duration::minutes(50)),
+            event(EventKind::Order, CA, "a", now - Duration::minutes(20)),
+            event(EventKind::Order, CA, "b", now - Duration::minutes(10)),
+            event(EventKind::Order, "other", "a", now - Duration::minutes(10)),
+            event(EventKind::Issued, CA, "a", now - Duration::minutes(10)),
+        ];
+        assert_eq!(free_at(&budget, 3), None);
+        assert_eq!(free_at(&budget, 2), Some(now + Duration::minutes(10)));
+        assert_eq!(free_at(&budget, 1), Some(now + Duration::minutes(40)));
+        // A limit of 0 is disabled.
+        assert_eq!(free_at(&budget, 0), None);
+    }
+
+    #[test]
+    fn check_reports_caps_and_the_latest_rate_limit() {
+        let now = Utc::now();
+        let mut budget = budget("check", limits(300, 1, 1));
+        assert!(budget.check(&profile(), now).is_ok());
+
+        budget.events = vec![
+            event(
+                EventKind::Issued,
+                CA,
+                "example.org",
+                now - Duration::days(6),
+            ),
+            event(
+                EventKind::FailedValidation,
+                CA,
+                "me@example.com example.com",
+                now - Duration::minutes(30),
+            ),
+        ];
+        match budget.check(&profile(), now) {
+            Err(Blocked::RateLimited(reason, at)) => {
+                assert!(reason.contains("example.org"), "{}", reason);
+                assert_eq!(at, now + Duration::days(1));
+            }
+            _ => panic!("expected a rate limit"),
+        }
+
+        budget.start(&profile());
+        assert!(matches!(
+            budget.check(&profile(), now),
+            Err(Blocked::Busy(_))
+        ));
+    }
+
+    #[test]
+    fn jobs_that_never_ran_lego_count_for_nothing() {
+        let mut budget = budget("no-lego", limits(300, 50, 5));
+        let failed = finished(
+            JobStatus::Failed,
+            Some("Template in 'cmd': unknown NOPE"),
+            &["Template in 'cmd': unknown NOPE"],
+        );
+        budget.start(&profile());
+        budget.finish(&profile(), Some(&failed));
+        let missing = finished(
+            JobStatus::Failed,
+            Some("Failed to start \"lego\": No such file or directory"),
+            &[
+                "$ lego run",
+                "Failed to start \"lego\": No such file or directory",
+            ],
+        );
+        budget.start(&profile());
+        budget.finish(&profile(), Some(&missing));
+        assert!(budget.events.is_empty());
+        assert!(budget.check(&profile(), Utc::now()).is_ok());
+        let _ = std::fs::remove_file(test_path("no-lego"));
+    }
+
+    #[test]
+    fn finished_runs_count_their_order_and_outcome() {
+        let mut budget = budget("outcomes", limits(300, 50, 5));
+        let succeeded = finished(JobStatus::Succeeded, None, &["$ lego run"]);
+        budget.finish(&profile(), Some(&succeeded));
+        assert_eq!(count(&budget, EventKind::Order), 1);
+        assert_eq!(count(&budget, EventKind::Issued), 2);
+
+        let dns = finished(
+            JobStatus::Failed,
+            Some("Lego command failed"),
+            &["$ lego run", "propagation: time limit exceeded"],
+        );
+        budget.finish(&profile(), Some(&dns));
+        assert_eq!(count(&budget, EventKind::Order), 2);
+        assert_eq!(count(&budget, EventKind::FailedValidation), 2);
+
+        let timeout = finished(
+            JobStatus::Failed,
+            Some("lego timed out after 600s"),
+            &["$ lego run"],
+        );
+        budget.finish(&profile(), Some(&timeout));
+        assert_eq!(count(&budget, EventKind::Order), 3);
+        assert_eq!(count(&budget, EventKind::FailedValidation), 2);
+        std::fs::remove_file(test_path("outcomes")).unwrap();
+    }
+}
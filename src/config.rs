@@ -1,12 +1,21 @@
 /* src/config.rs */
 
+use crate::pause::{self, Hold};
+use crate::redact;
+use crate::renewal::RenewBefore;
+use crate::retry::{RetryOverrides, RetryPolicy};
+use crate::schedule::{CronSchedule, MaintenanceWindow};
+use crate::secrets::SecretSource;
+use crate::template;
+use crate::validate::{self, NameError};
 use fancy_log::{LogLevel, log};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::fs;
-use toml_edit::{DocumentMut, Table, value};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, value};
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
@@ -14,6 +23,38 @@ pub struct AppConfig {
     pub update_interval: Duration,
     pub dir_path: PathBuf,
     pub bind_port: u16,
+    pub allow_ip_certificates: bool,
+    /// Jobs that may run at the same time across all providers.
+    pub max_concurrent_jobs: usize,
+    /// Default per-provider cap; a provider's `max_concurrent` overrides it.
+    pub max_jobs_per_provider: usize,
+    pub max_jobs_per_ca: usize,
+    pub rate_limits: RateLimits,
+    pub retry: RetryPolicy,
+    /// When renewal checks run; `None` runs them every `update_interval`.
+    pub renewal_schedule: Option<CronSchedule>,
+    /// Up to this much random delay is added to each renewal check.
+    pub renewal_jitter: Duration,
+    /// Default for each certificate's `renew_before`; `None` renews 30 days
+    /// before expiry, or with a third of the lifetime left if that is shorter.
+    pub renew_before: Option<RenewBefore>,
+    /// Certificates expiring within this many days renew outside their
+    /// maintenance window.
+    pub critical_expiry_days: i64,
+    /// Size at which `history.jsonl` is rotated.
+    pub history_max_bytes: u64,
+}
+
+/// The CA limits tracked locally, per ACME directory. Zero disables a limit.
+/// The defaults are Let's Encrypt's published limits.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// New orders per account per 3 hours.
+    pub orders_per_3h: usize,
+    /// Certificates per registered domain per 7 days.
+    pub certs_per_domain_per_week: usize,
+    /// Failed validations per account and hostname per hour.
+    pub failed_validations_per_hour: usize,
 }
 
 impl AppConfig {
@@ -37,33 +78,487 @@ impl AppConfig {
             .unwrap_or_else(|_| "33301".to_string())
             .parse::<u16>()
             .unwrap_or(33301);
+        let allow_ip_certificates = env::var("ALLOW_IP_CERTIFICATES")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        let renewal_schedule = env::var("RENEWAL_SCHEDULE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .and_then(|expr| match CronSchedule::parse(&expr) {
+                Ok(schedule) => Some(schedule),
+                Err(e) => {
+                    log(
+                        LogLevel::Error,
+                        &format!("RENEWAL_SCHEDULE: {}; using UPDATE_INTERVAL_HOURS", e),
+                    );
+                    None
+                }
+            });
+        let renew_before = env::var("RENEW_BEFORE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .and_then(|value| match RenewBefore::parse(&value) {
+                Ok(renew_before) => Some(renew_before),
+                Err(e) => {
+                    log(
+                        LogLevel::Error,
+                        &format!("RENEW_BEFORE: {}; using the default", e),
+                    );
+                    None
+                }
+            });
         Self {
             log_level,
             update_interval,
             dir_path,
             bind_port,
+            allow_ip_certificates,
+            max_concurrent_jobs: env_usize("MAX_CONCURRENT_JOBS", 4).max(1),
+            max_jobs_per_provider: env_usize("MAX_JOBS_PER_PROVIDER", 1).max(1),
+            max_jobs_per_ca: env_usize("MAX_JOBS_PER_CA", 2).max(1),
+            rate_limits: RateLimits {
+                orders_per_3h: env_usize("RATE_LIMIT_ORDERS_PER_3H", 300),
+                certs_per_domain_per_week: env_usize("RATE_LIMIT_CERTS_PER_DOMAIN_PER_WEEK", 50),
+                failed_validations_per_hour: env_usize("RATE_LIMIT_FAILED_VALIDATIONS_PER_HOUR", 5),
+            },
+            retry: RetryPolicy {
+                max_attempts: env_usize("RETRY_MAX_ATTEMPTS", 5).max(1) as u32,
+                base_delay: Duration::from_secs(env_usize("RETRY_BASE_DELAY_SECS", 300) as u64),
+                max_delay: Duration::from_secs(env_usize("RETRY_MAX_DELAY_SECS", 21600) as u64),
+                jitter: env::var("RETRY_JITTER")
+                    .ok()
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .unwrap_or(0.2)
+                    .clamp(0.0, 1.0),
+            },
+            renewal_schedule,
+            renewal_jitter: Duration::from_secs(env_usize("RENEWAL_JITTER_SECS", 0) as u64),
+            renew_before,
+            critical_expiry_days: env_usize("CRITICAL_EXPIRY_DAYS", 7) as i64,
+            history_max_bytes: env_usize("HISTORY_MAX_MB", 10).max(1) as u64 * 1024 * 1024,
         }
     }
 }
 
+fn env_usize(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct DomainEntry {
     pub name: String,
     pub dns_provider: String,
+    #[serde(default)]
+    pub retry: RetryOverrides,
+    pub maintenance_window: Option<MaintenanceWindow>,
+    pub renew_before: Option<RenewBefore>,
+    #[serde(default, deserialize_with = "pause::deserialize_flag")]
+    pub disabled: Option<Hold>,
+}
+
+/// A SAN is either a bare name or a name with its own DNS provider.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SanEntry {
+    Name(String),
+    Routed { name: String, dns_provider: String },
+}
+
+#[derive(Deserialize)]
+struct RawCertificateEntry {
+    id: String,
+    sans: Vec<SanEntry>,
+    dns_provider: String,
+    #[serde(default)]
+    retry: RetryOverrides,
+    maintenance_window: Option<MaintenanceWindow>,
+    renew_before: Option<RenewBefore>,
+    #[serde(default, deserialize_with = "pause::deserialize_flag")]
+    disabled: Option<Hold>,
+}
+
+/// A certificate with a stable `id` and an explicit list of names.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "RawCertificateEntry")]
+pub struct CertificateEntry {
+    pub id: String,
+    pub sans: Vec<String>,
+    pub dns_provider: String,
+    /// SANs whose challenges go through a provider other than `dns_provider`.
+    pub san_providers: BTreeMap<String, String>,
+    /// Unicode display names of IDN SANs, keyed by their punycode form.
+    pub display_names: BTreeMap<String, String>,
+    pub retry: RetryOverrides,
+    /// When renewals may run; outside it they wait unless expiry is close.
+    pub maintenance_window: Option<MaintenanceWindow>,
+    /// Overrides the global `RENEW_BEFORE`.
+    pub renew_before: Option<RenewBefore>,
+    /// Keeps lazy-acme from running lego for this certificate.
+    pub disabled: Option<Hold>,
+}
+
+impl From<RawCertificateEntry> for CertificateEntry {
+    fn from(raw: RawCertificateEntry) -> Self {
+        CertificateEntry {
+            retry: raw.retry,
+            maintenance_window: raw.maintenance_window,
+            renew_before: raw.renew_before,
+            disabled: raw.disabled,
+            ..CertificateEntry::new(raw.id, raw.sans, raw.dns_provider)
+        }
+    }
+}
+
+impl CertificateEntry {
+    pub fn new(id: String, sans: Vec<SanEntry>, dns_provider: String) -> Self {
+        let dns_provider = dns_provider.trim().to_string();
+        let mut names = Vec::new();
+        let mut san_providers = BTreeMap::new();
+        for san in sans {
+            match san {
+                SanEntry::Name(name) => names.push(name.trim().to_string()),
+                SanEntry::Routed {
+                    name,
+                    dns_provider: provider,
+                } => {
+                    let name = name.trim().to_string();
+                    let provider = provider.trim().to_string();
+                    if provider != dns_provider {
+                        san_providers.insert(name.clone(), provider);
+                    }
+                    names.push(name);
+                }
+            }
+        }
+        CertificateEntry {
+            id: id.trim().to_string(),
+            sans: names,
+            dns_provider,
+            san_providers,
+            display_names: BTreeMap::new(),
+            retry: RetryOverrides::default(),
+            maintenance_window: None,
+            renew_before: None,
+            disabled: None,
+        }
+    }
+
+    /// Checks the id, provider names and every SAN, and normalizes SANs to
+    /// their ASCII (punycode) form.
+    pub fn validate(&mut self, allow_ip: bool) -> Result<(), String> {
+        // A `[[domains]]` entry uses its name as id, which may be an IDN.
+        if self.is_legacy_shape() {
+            let name = validate::validate_domain(&self.id, allow_ip)
+                .map_err(|e| format!("domain '{}': {}", self.id, e))?;
+            if name.ascii.starts_with("*.") {
+                return Err(format!(
+                    "domain '{}': {}",
+                    self.id,
+                    NameError::MisplacedWildcard(self.id.clone())
+                ));
+            }
+            self.id = name.ascii;
+            self.sans = legacy_sans(&self.id);
+        }
+
+        let id = self.id.clone();
+        let context = |e: NameError| format!("certificate '{}': {}", id, e);
+
+        self.id = validate::validate_identifier(&self.id).map_err(context)?;
+        self.dns_provider = validate::validate_identifier(&self.dns_provider).map_err(context)?;
+        if let Some(window) = &self.maintenance_window {
+            window
+                .validate()
+                .map_err(|e| format!("certificate '{}': {}", id, e))?;
+        }
+        if self.sans.is_empty() {
+            return Err(context(NameError::Empty));
+        }
+
+        let mut sans = Vec::new();
+        let mut san_providers = BTreeMap::new();
+        let mut display_names = BTreeMap::new();
+        for san in &self.sans {
+            let name = validate::validate_domain(san, allow_ip).map_err(context)?;
+            if sans.contains(&name.ascii) {
+                return Err(format!(
+                    "certificate '{}': '{}' is listed more than once",
+                    id, name.ascii
+                ));
+            }
+            if let Some(provider) = self.san_providers.get(san) {
+                let provider = validate::validate_identifier(provider).map_err(context)?;
+                san_providers.insert(name.ascii.clone(), provider);
+            }
+            if name.display != name.ascii {
+                display_names.insert(name.ascii.clone(), name.display);
+            }
+            sans.push(name.ascii);
+        }
+        self.sans = sans;
+        self.san_providers = san_providers;
+        self.display_names = display_names;
+        Ok(())
+    }
+
+    /// The SANs in the form they are configured in, for writing the entry back.
+    pub fn san_entries(&self) -> Vec<SanEntry> {
+        self.sans
+            .iter()
+            .map(|san| match self.san_providers.get(san) {
+                Some(provider) => SanEntry::Routed {
+                    name: san.clone(),
+                    dns_provider: provider.clone(),
+                },
+                None => SanEntry::Name(san.clone()),
+            })
+            .collect()
+    }
+
+    /// The global retry policy with this certificate's overrides applied.
+    pub fn retry_policy(&self, config: &AppConfig) -> RetryPolicy {
+        config.retry.with(&self.retry)
+    }
+
+    pub fn renew_before(&self, config: &AppConfig) -> Option<RenewBefore> {
+        self.renew_before.or(config.renew_before)
+    }
+
+    /// The SANs as they should be shown to people.
+    pub fn display_sans(&self) -> Vec<String> {
+        self.sans
+            .iter()
+            .map(|san| self.display_names.get(san).unwrap_or(san).clone())
+            .collect()
+    }
+
+    /// The DNS provider that answers the challenge for `san`.
+    pub fn provider_for(&self, san: &str) -> &str {
+        self.san_providers
+            .get(san)
+            .map(String::as_str)
+            .unwrap_or(&self.dns_provider)
+    }
+
+    /// Every DNS provider this certificate uses, the default one first.
+    pub fn providers(&self) -> Vec<&str> {
+        let mut providers = vec![self.dns_provider.as_str()];
+        for provider in self.san_providers.values() {
+            if !providers.contains(&provider.as_str()) {
+                providers.push(provider);
+            }
+        }
+        providers
+    }
+
+    /// The primary name of the certificate, used for `{{DOMAIN}}`.
+    pub fn primary_domain(&self) -> &str {
+        self.sans
+            .first()
+            .map(|san| san.trim_start_matches("*."))
+            .unwrap_or(&self.id)
+    }
+
+    /// The file name lego gives this certificate: its first SAN, with `*`
+    /// replaced by `_` and `:` by `-`.
+    pub fn file_stem(&self) -> String {
+        self.sans
+            .first()
+            .unwrap_or(&self.id)
+            .replace('*', "_")
+            .replace(':', "-")
+    }
+
+    /// Whether `other` asks for the same certificate: the same id, names and
+    /// DNS providers.
+    pub fn same_certificate(&self, other: &CertificateEntry) -> bool {
+        self.id == other.id
+            && self.sans == other.sans
+            && self.dns_provider == other.dns_provider
+            && self.san_providers == other.san_providers
+    }
+
+    /// Why this entry cannot be managed alongside `other`: both have the
+    /// same id, or lego would write both to the same files.
+    pub fn conflict_with(&self, other: &CertificateEntry) -> Option<String> {
+        if self.id == other.id {
+            return Some(format!("certificate '{}': id is already in use", self.id));
+        }
+        if self.file_stem() == other.file_stem() {
+            return Some(format!(
+                "certificate '{}': its first SAN '{}' is also the first SAN of '{}', and lego names the files after it",
+                self.id,
+                self.sans.first().unwrap_or(&self.id),
+                other.id
+            ));
+        }
+        None
+    }
+
+    /// Validates the entry as one more in a config whose valid entries so far
+    /// are `earlier`.
+    pub fn validate_alongside(
+        &mut self,
+        allow_ip: bool,
+        earlier: &[CertificateEntry],
+    ) -> Result<(), String> {
+        self.validate(allow_ip)?;
+        match earlier.iter().find_map(|other| self.conflict_with(other)) {
+            Some(conflict) => Err(conflict),
+            None => Ok(()),
+        }
+    }
+
+    fn is_legacy_shape(&self) -> bool {
+        self.sans == legacy_sans(&self.id) && self.san_providers.is_empty()
+    }
+}
+
+impl From<&DomainEntry> for CertificateEntry {
+    fn from(domain: &DomainEntry) -> Self {
+        let name = domain.name.trim();
+        CertificateEntry {
+            id: name.to_string(),
+            sans: legacy_sans(name),
+            dns_provider: domain.dns_provider.trim().to_string(),
+            san_providers: BTreeMap::new(),
+            display_names: BTreeMap::new(),
+            retry: domain.retry,
+            maintenance_window: domain.maintenance_window.clone(),
+            renew_before: domain.renew_before,
+            disabled: domain.disabled.clone(),
+        }
+    }
+}
+
+/// A `[[domains]]` entry always covers the wildcard and the apex.
+pub fn legacy_sans(name: &str) -> Vec<String> {
+    vec![format!("*.{}", name), name.to_string()]
 }
 
 #[derive(Deserialize, Debug)]
 pub struct DomainConfig {
+    /// Pauses the scheduler: `pause = { reason = "..", until = ".." }`.
+    #[serde(default)]
+    pub pause: Option<Hold>,
     #[serde(default, rename = "domains")]
     pub domains: Vec<DomainEntry>,
+    #[serde(default, rename = "certificates")]
+    pub certificates: Vec<CertificateEntry>,
+}
+
+impl DomainConfig {
+    /// Every configured certificate as written, with `[[domains]]` entries converted.
+    pub fn raw_entries(&self) -> Vec<CertificateEntry> {
+        self.domains
+            .iter()
+            .map(CertificateEntry::from)
+            .chain(self.certificates.iter().cloned())
+            .collect()
+    }
+
+    /// Every valid managed certificate. Invalid entries, and entries that
+    /// conflict with an earlier one, are logged and skipped.
+    pub fn entries(&self, allow_ip: bool) -> Vec<CertificateEntry> {
+        let mut entries: Vec<CertificateEntry> = Vec::new();
+        for mut entry in self.raw_entries() {
+            match entry.validate_alongside(allow_ip, &entries) {
+                Ok(()) => entries.push(entry),
+                Err(e) => log(
+                    LogLevel::Error,
+                    &format!("Skipping invalid entry in config.toml: {}", e),
+                ),
+            }
+        }
+        entries
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderType {
+    /// lego is run directly with `--dns <dns>`, `env` and extra `args`.
+    #[default]
+    Lego,
+    /// Legacy: `cmd` / `renew` are shell command templates run through `sh -c`.
+    /// Values are substituted into a shell command line, so this must be
+    /// chosen explicitly.
+    Shell,
+    /// lego is driven through its `exec` DNS provider, which calls back into
+    /// lazy-acme, which in turn runs the `present` / `cleanup` executables.
+    Exec,
+    /// Like `Exec`, but `present` / `cleanup` describe HTTP requests.
+    Webhook,
+}
+
+/// What to call for one hook action: an executable or an HTTP request.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum HookTarget {
+    Program(String),
+    Http(WebhookRequest),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookRequest {
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    /// Accepted response codes; any 2xx when empty.
+    #[serde(default)]
+    pub expect_status: Vec<u16>,
+    #[serde(default, rename = "assert")]
+    pub assertions: Vec<JsonAssertion>,
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+/// A check against the JSON response body, addressed by a JSONPath.
+#[derive(Deserialize, Debug, Clone)]
+pub struct JsonAssertion {
+    pub path: String,
+    pub equals: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct DnsProviderConfig {
-    pub cmd: String,
+    #[serde(default, rename = "type")]
+    pub provider_type: ProviderType,
+    /// The lego DNS provider code, e.g. `cloudflare`.
+    pub dns: Option<String>,
+    /// Environment for lego; values are templates.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Extra global lego arguments; each one is a template.
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub run_args: Vec<String>,
+    pub renew_args: Option<Vec<String>>,
+    /// Seconds a lego run may take before it is killed.
+    pub timeout: Option<u64>,
+    /// Jobs using this provider that may run at the same time.
+    pub max_concurrent: Option<usize>,
+    pub cmd: Option<String>,
     pub renew: Option<String>,
+    pub present: Option<HookTarget>,
+    pub cleanup: Option<HookTarget>,
+    /// Variable and env names to keep out of logs, besides key-like names.
+    #[serde(default)]
+    pub secrets: Vec<String>,
     #[serde(flatten)]
     pub vars: toml::map::Map<String, toml::Value>,
+    /// Values of secret-source variables, filled in by `secrets::resolve`.
+    #[serde(skip)]
+    pub resolved: BTreeMap<String, String>,
 }
 
 pub async fn load_domain_config(
@@ -77,30 +572,155 @@ pub async fn load_dns_provider_config(
     path: &Path,
 ) -> Result<DnsProviderConfig, Box<dyn std::error::Error + Send + Sync>> {
     let content = fs::read_to_string(path).await?;
-    Ok(toml::from_str(&content)?)
+    let provider_config: DnsProviderConfig = toml::from_str(&content)?;
+    provider_config.validate_templates()?;
+    Ok(provider_config)
 }
 
-pub async fn add_domain_to_config(
+impl DnsProviderConfig {
+    /// Checks that every template parses and only uses built-ins, variables
+    /// of this config, or placeholders with a default.
+    pub fn validate_templates(&self) -> Result<(), String> {
+        let is_var = |name: &str| self.vars.keys().any(|k| k.eq_ignore_ascii_case(name));
+        let cert_known = |name: &str| template::CERT_BUILTINS.contains(&name) || is_var(name);
+        let hook_known = |name: &str| template::HOOK_BUILTINS.contains(&name) || is_var(name);
+
+        let mut cert_templates: Vec<(String, &str)> = self
+            .env
+            .iter()
+            .map(|(key, value)| (format!("env.{}", key), value.as_str()))
+            .collect();
+        for (field, list) in [
+            ("args", Some(&self.args)),
+            ("run_args", Some(&self.run_args)),
+            ("renew_args", self.renew_args.as_ref()),
+        ] {
+            cert_templates.extend(
+                list.into_iter()
+                    .flatten()
+                    .map(|a| (field.to_string(), a.as_str())),
+            );
+        }
+        cert_templates.extend(self.cmd.as_deref().map(|t| ("cmd".to_string(), t)));
+        cert_templates.extend(self.renew.as_deref().map(|t| ("renew".to_string(), t)));
+        for (field, value) in cert_templates {
+            template::validate(value, cert_known)
+                .map_err(|e| format!("Template in '{}': {}", field, e))?;
+        }
+
+        for (action, target) in [("present", &self.present), ("cleanup", &self.cleanup)] {
+            let Some(HookTarget::Http(webhook)) = target else {
+                continue;
+            };
+            let mut hook_templates = vec![webhook.url.as_str()];
+            hook_templates.extend(webhook.headers.values().map(String::as_str));
+            hook_templates.extend(webhook.body.as_deref());
+            for value in hook_templates {
+                template::validate(value, hook_known)
+                    .map_err(|e| format!("Template in '{}' webhook: {}", action, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the variable or env name `name` holds a secret, either because
+    /// it is listed in `secrets` or because it looks like one.
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets.iter().any(|s| s.eq_ignore_ascii_case(name)) || redact::is_secret_name(name)
+    }
+
+    /// The values of every secret variable. Values from a secret source are
+    /// always included, whatever their name.
+    pub fn secret_values(&self) -> Vec<String> {
+        self.vars
+            .keys()
+            .filter(|key| self.is_secret(key) && !self.resolved.contains_key(*key))
+            .filter_map(|key| self.var_str(key))
+            .chain(self.resolved.values().cloned())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    /// Looks up a provider variable by name, ignoring case, as a string.
+    /// Secret-source variables only have a value once resolved.
+    pub fn var_str(&self, key: &str) -> Option<String> {
+        let (name, value) = self
+            .vars
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))?;
+        match value {
+            toml::Value::String(s) => Some(s.clone()),
+            toml::Value::Integer(i) => Some(i.to_string()),
+            _ => self.resolved.get(name).cloned(),
+        }
+    }
+
+    /// Whether `key` is a `{ env | file | exec }` variable.
+    pub fn is_secret_source(&self, key: &str) -> bool {
+        self.vars
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case(key) && SecretSource::from_value(v).is_some())
+    }
+}
+
+pub async fn add_certificate_to_config(
     config_path: &Path,
-    domain: &str,
-    dns_provider: &str,
+    entry: &CertificateEntry,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let content = fs::read_to_string(config_path).await?;
+    let configured: DomainConfig = toml::from_str(&content)?;
+    let already_configured = configured
+        .raw_entries()
+        .into_iter()
+        .any(|mut other| other.validate(true).is_ok() && other.id == entry.id);
+    if already_configured {
+        log(
+            LogLevel::Debug,
+            &format!("Certificate '{}' is already in config.toml", entry.id),
+        );
+        return Ok(());
+    }
     log(
         LogLevel::Info,
-        &format!("Persisting new domain '{}' to config.toml", domain),
+        &format!("Persisting new certificate '{}' to config.toml", entry.id),
     );
-    let content = fs::read_to_string(config_path).await?;
     let mut doc = content.parse::<DocumentMut>()?;
 
-    let domains_array = doc["domains"]
-        .as_array_of_tables_mut()
-        .ok_or("config.toml is missing 'domains' array of tables")?;
-
-    let mut new_domain_table = Table::new();
-    new_domain_table["name"] = value(domain);
-    new_domain_table["dns_provider"] = value(dns_provider);
+    // Entries that look like a `[[domains]]` entry are written back as one.
+    let (key, mut new_table) = if entry.is_legacy_shape() {
+        let mut table = Table::new();
+        table["name"] = value(&entry.id);
+        ("domains", table)
+    } else {
+        let mut table = Table::new();
+        table["id"] = value(&entry.id);
+        let mut sans = Array::new();
+        for san in &entry.sans {
+            match entry.san_providers.get(san) {
+                Some(provider) => {
+                    let mut routed = InlineTable::new();
+                    routed.insert("name", san.into());
+                    routed.insert("dns_provider", provider.into());
+                    sans.push(routed);
+                }
+                None => sans.push(san),
+            }
+        }
+        table["sans"] = value(sans);
+        ("certificates", table)
+    };
+    new_table["dns_provider"] = value(&entry.dns_provider);
 
-    domains_array.push(new_domain_table);
+    doc.entry(key)
+        .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| {
+            format!(
+                "config.toml has a '{}' key that is not an array of tables",
+                key
+            )
+        })?
+        .push(new_table);
 
     fs::write(config_path, doc.to_string()).await?;
     Ok(())
@@ -0,0 +1,286 @@
+/* src/doctor.rs */
+
+use crate::{
+    acme::CommandType,
+    config::{self, AppConfig, CertificateEntry},
+    lego, secrets,
+};
+use fancy_log::{LogLevel, log};
+use std::collections::BTreeSet;
+use std::path::Path;
+use tokio::{fs, process::Command};
+use x509_parser::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Results of a preflight run, in the order the checks ran.
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|c| c.status == CheckStatus::Fail)
+    }
+
+    pub fn print(&self) {
+        for check in &self.checks {
+            let (level, label) = match check.status {
+                CheckStatus::Pass => (LogLevel::Info, "PASS"),
+                CheckStatus::Warn => (LogLevel::Warn, "WARN"),
+                CheckStatus::Fail => (LogLevel::Error, "FAIL"),
+            };
+            log(
+                level,
+                &format!("[{}] {}: {}", label, check.name, check.detail),
+            );
+        }
+        let failures = self
+            .checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .count();
+        if failures == 0 {
+            log(LogLevel::Info, "Doctor: all checks passed.");
+        } else {
+            log(
+                LogLevel::Error,
+                &format!("Doctor: {} check(s) failed.", failures),
+            );
+        }
+    }
+}
+
+/// Checks everything a lego run depends on, without contacting the CA.
+pub async fn run(config: &AppConfig) -> Report {
+    let mut report = Report::default();
+    check_lego(&mut report).await;
+    check_data_dir(&mut report, &config.dir_path).await;
+    if let Some(entries) = check_config(&mut report, config).await {
+        check_providers(&mut report, config, &entries).await;
+    }
+    check_certificates(&mut report, &config.dir_path.join(".lego/certificates")).await;
+    report
+}
+
+async fn check_lego(report: &mut Report) {
+    match Command::new(lego::LEGO_PROGRAM)
+        .arg("--version")
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            report.push("lego", CheckStatus::Pass, version);
+        }
+        Ok(output) => report.push(
+            "lego",
+            CheckStatus::Fail,
+            format!("'lego --version' exited with {}", output.status),
+        ),
+        Err(e) => report.push(
+            "lego",
+            CheckStatus::Fail,
+            format!("'{}' not found on PATH: {}", lego::LEGO_PROGRAM, e),
+        ),
+    }
+}
+
+async fn check_data_dir(report: &mut Report, dir: &Path) {
+    let probe = dir.join(".doctor-write-test");
+    let result = match fs::write(&probe, b"ok").await {
+        Ok(()) => fs::remove_file(&probe).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => report.push(
+            "data dir",
+            CheckStatus::Pass,
+            format!("{:?} is writable", dir),
+        ),
+        Err(e) => report.push(
+            "data dir",
+            CheckStatus::Fail,
+            format!("{:?} is not writable: {}", dir, e),
+        ),
+    }
+}
+
+/// Returns the valid certificate entries when config.toml could be parsed.
+async fn check_config(report: &mut Report, config: &AppConfig) -> Option<Vec<CertificateEntry>> {
+    let path = config.dir_path.join("config.toml");
+    let domain_config = match config::load_domain_config(&path).await {
+        Ok(c) => c,
+        Err(e) => {
+            report.push("config.toml", CheckStatus::Fail, e.to_string());
+            return None;
+        }
+    };
+
+    let mut entries = Vec::new();
+    for mut entry in domain_config.raw_entries() {
+        let id = entry.id.clone();
+        match entry.validate_alongside(config.allow_ip_certificates, &entries) {
+            Ok(()) => entries.push(entry),
+            Err(e) => report.push(format!("certificate '{}'", id), CheckStatus::Fail, e),
+        }
+    }
+    report.push(
+        "config.toml",
+        CheckStatus::Pass,
+        format!("{} certificate(s) configured", entries.len()),
+    );
+    Some(entries)
+}
+
+async fn check_providers(report: &mut Report, config: &AppConfig, entries: &[CertificateEntry]) {
+    let providers: BTreeSet<&str> = entries.iter().flat_map(|e| e.providers()).collect();
+    let mut broken = BTreeSet::new();
+    for provider in providers {
+        let name = format!("{}.dns.toml", provider);
+        let path = config.dir_path.join(&name);
+        if fs::metadata(&path).await.is_err() {
+            report.push(name, CheckStatus::Fail, "file not found");
+            broken.insert(provider);
+            continue;
+        }
+        match config::load_dns_provider_config(&path).await {
+            Ok(mut provider_config) => {
+                if let Err(e) = secrets::resolve(provider, &mut provider_config).await {
+                    report.push(name, CheckStatus::Fail, e.to_string());
+                    broken.insert(provider);
+                    continue;
+                }
+                let unresolved = lego::unresolved_placeholders(&provider_config);
+                if unresolved.is_empty() {
+                    report.push(name, CheckStatus::Pass, "parsed, all placeholders resolve");
+                } else {
+                    report.push(
+                        name,
+                        CheckStatus::Fail,
+                        format!("placeholders render empty: {}", unresolved.join(", ")),
+                    );
+                    broken.insert(provider);
+                }
+            }
+            Err(e) => {
+                report.push(name, CheckStatus::Fail, e.to_string());
+                broken.insert(provider);
+            }
+        }
+    }
+
+    // Building the real invocation catches the rest: missing `dns`/`email`,
+    // conflicting SAN routes and the like.
+    for entry in entries {
+        if entry.providers().iter().any(|p| broken.contains(p)) {
+            continue;
+        }
+        if let Err(e) = lego::check_routing(entry, config).await {
+            report.push(format!("certificate '{}'", entry.id), CheckStatus::Fail, e);
+            continue;
+        }
+        if let Err(e) = lego::build_invocation(entry, config, CommandType::Run).await {
+            report.push(
+                format!("certificate '{}'", entry.id),
+                CheckStatus::Fail,
+                e.to_string(),
+            );
+        }
+    }
+}
+
+async fn check_certificates(report: &mut Report, cert_dir: &Path) {
+    let Ok(mut dir) = fs::read_dir(cert_dir).await else {
+        return;
+    };
+    let mut checked = 0;
+    while let Ok(Some(file)) = dir.next_entry().await {
+        let path = file.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(".crt") else {
+            continue;
+        };
+        if stem.ends_with(".issuer") {
+            continue;
+        }
+        checked += 1;
+        let key_path = cert_dir.join(format!("{}.key", stem));
+        let label = format!("certificate {}", stem);
+        match key_matches(&path, &key_path).await {
+            Ok(Some(true)) => report.push(label, CheckStatus::Pass, "key matches certificate"),
+            Ok(None) => report.push(
+                label,
+                CheckStatus::Warn,
+                "key type cannot be checked, skipped",
+            ),
+            Ok(Some(false)) => report.push(
+                label,
+                CheckStatus::Fail,
+                "key does not belong to certificate",
+            ),
+            Err(e) => report.push(label, CheckStatus::Fail, e.to_string()),
+        }
+    }
+    if checked == 0 {
+        report.push(
+            "certificates",
+            CheckStatus::Pass,
+            "no certificates issued yet",
+        );
+    }
+}
+
+/// The certificate's public key (EC point or RSA modulus) is always embedded
+/// in the private key lego writes (SEC1 / PKCS#1, possibly inside PKCS#8),
+/// so finding it there proves the pair belongs together. Other key types
+/// return `None`.
+async fn key_matches(
+    cert_path: &Path,
+    key_path: &Path,
+) -> Result<Option<bool>, Box<dyn std::error::Error + Send + Sync>> {
+    let cert_pem = ::pem::parse(fs::read(cert_path).await?)?;
+    let (_, cert) = X509Certificate::from_der(cert_pem.contents())?;
+    let key_pem = ::pem::parse(
+        fs::read(key_path)
+            .await
+            .map_err(|e| format!("cannot read {:?}: {}", key_path, e))?,
+    )?;
+
+    let public = match cert.public_key().parsed()? {
+        x509_parser::public_key::PublicKey::EC(point) => point.data().to_vec(),
+        x509_parser::public_key::PublicKey::RSA(rsa) => {
+            let start = rsa.modulus.iter().take_while(|b| **b == 0).count();
+            rsa.modulus[start..].to_vec()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(
+        key_pem
+            .contents()
+            .windows(public.len())
+            .any(|window| window == public.as_slice()),
+    ))
+}
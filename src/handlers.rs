@@ -1,9 +1,17 @@
 /* src/handlers.rs */
 
 use crate::{
-    acme::{self, CommandType},
+    acme,
+    config::{CertificateEntry, DomainEntry, SanEntry},
+    history::{HistoryEntry, HistoryFilter},
+    jobs::{JobKind, JobStatus, Trigger},
+    lego,
+    metadata::CertificateMetadata,
+    pause::Hold,
+    queue::{self, Enqueued},
     response,
     state::{AppState, DomainStatus},
+    tasks,
 };
 use axum::{
     Json,
@@ -12,15 +20,115 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use base64::{Engine as _, engine::general_purpose::STANDARD};
+use chrono::Utc;
 use fancy_log::{LogLevel, log};
 use serde::Deserialize;
 use serde_json::json;
-use std::path::PathBuf;
+use std::collections::HashMap;
 use tokio::fs;
 
+/// Renewal scheduler state plus the health of every managed certificate:
+/// which ones are failing or held back, and what that blocks.
 pub async fn get_task_status(State(state): State<AppState>) -> Response {
     let is_running = *state.task_running.read();
-    response::success(Some(json!({ "running": is_running })))
+    let mut ids: Vec<String> = state.certificates.read().keys().cloned().collect();
+    ids.sort();
+
+    // The running or next queued job of each certificate.
+    let mut active: HashMap<String, (String, JobKind, &'static str)> = HashMap::new();
+    {
+        let queue = state.queue.read();
+        for job in queue.running() {
+            active.insert(
+                job.entry.id.clone(),
+                (job.job_id.clone(), job.kind, "running"),
+            );
+        }
+        for job in queue.pending() {
+            active
+                .entry(job.entry.id.clone())
+                .or_insert((job.job_id.clone(), job.kind, "queued"));
+        }
+    }
+
+    let mut healthy = true;
+    let mut domains = Vec::new();
+    for id in ids {
+        let record = state.domains.read().record(&id).cloned();
+        let status = record.as_ref().map(|r| r.status.clone());
+        let has_certificate = state.metadata.read().contains_key(&id);
+        let job = active.get(&id).map(|(job_id, kind, job_state)| {
+            let jobs = state.jobs.read();
+            let record = jobs.get(job_id);
+            json!({
+                "id": job_id,
+                "kind": kind,
+                "state": job_state,
+                "delay_reason": record.and_then(|j| j.delay_reason.clone()),
+                "delayed_until": record.and_then(|j| j.delayed_until),
+            })
+        });
+        let delay_reason = job
+            .as_ref()
+            .and_then(|j| j["delay_reason"].as_str().map(str::to_string));
+        let blocks = if has_certificate {
+            "renewal"
+        } else {
+            "issuance"
+        };
+
+        let disabled = tasks::disabled_hold(&state, &id);
+        let (health, blocked_by) = match (&status, delay_reason) {
+            (Some(DomainStatus::Failed { reason, .. }), _) => ("failing", Some(reason.clone())),
+            _ if disabled.is_some() => (
+                "disabled",
+                disabled
+                    .as_ref()
+                    .map(|hold| hold.describe("certificate is disabled")),
+            ),
+            (_, Some(reason)) => ("blocked", Some(reason)),
+            (Some(DomainStatus::Ready), None) if job.is_none() => ("ok", None),
+            (None, None) if job.is_none() => ("unchecked", None),
+            _ => ("pending", None),
+        };
+        healthy &= health != "failing";
+
+        let (attempts, next_retry_at) = match &status {
+            Some(DomainStatus::Failed {
+                attempts,
+                next_retry_at,
+                ..
+            }) => (Some(*attempts), *next_retry_at),
+            _ => (None, None),
+        };
+        let renewal = state.renewals.read().get(&id);
+        domains.push(json!({
+            "id": id,
+            "status": status.as_ref().map(DomainStatus::name),
+            "status_since": record.as_ref().map(|r| r.since),
+            "last_attempt_at": record.as_ref().and_then(|r| r.last_attempt_at),
+            "interrupted": record.as_ref().is_some_and(|r| r.interrupted),
+            "health": health,
+            "disabled": disabled,
+            "has_certificate": has_certificate,
+            "blocks": blocked_by.as_ref().map(|_| blocks),
+            "blocked_by": blocked_by,
+            "attempts": attempts,
+            "next_retry_at": next_retry_at,
+            "expires_at": renewal.map(|r| r.expires_at),
+            "renew_at": renewal.map(|r| r.renew_at),
+            "job": job,
+        }));
+    }
+
+    response::success(Some(json!({
+        "running": is_running,
+        "paused": state.holds.read().paused(Utc::now()).cloned(),
+        "next_check_at": *state.next_renewal_check.read(),
+        "next_renewal_at": state.renewals.read().next_due(),
+        "healthy": healthy,
+        "domains": domains,
+    })))
 }
 
 #[derive(Deserialize)]
@@ -36,25 +144,25 @@ pub async fn get_certificate(
 ) -> Response {
     let domain_status = state.domains.read().get(domain.trim()).cloned();
 
-    match domain_status {
-        Some(DomainStatus::Ready) => {
+    let entry = state.certificates.read().get(domain.trim()).cloned();
+
+    match (domain_status, entry) {
+        (Some(DomainStatus::Ready), Some(entry)) => {
             let cert_dir = state.config.dir_path.join(".lego/certificates");
-            let domain_name = domain.trim();
-
-            let paths_to_try: Vec<PathBuf> = if query.wildcard {
-                vec![cert_dir.join(format!("_.{}.crt", domain_name))]
-            } else {
-                vec![
-                    cert_dir.join(format!("_.{}.crt", domain_name)),
-                    cert_dir.join(format!("{}.crt", domain_name)),
-                ]
+            let resource = match issued_resource(&state, &entry, query.wildcard) {
+                Ok(resource) => resource,
+                Err((status, message)) => return response::error(status, message),
             };
 
-            for path in paths_to_try {
-                if let Ok(content_bytes) = fs::read(&path).await {
-                    let encoded_cert = STANDARD.encode(&content_bytes);
-                    return response::success(Some(json!({ "certificate_base64": encoded_cert })));
-                }
+            if let Ok(content_bytes) = fs::read(resource.path(&cert_dir, "crt")).await {
+                let encoded_cert = STANDARD.encode(&content_bytes);
+                return response::success(Some(json!({
+                    "id": entry.id,
+                    "sans": entry.sans,
+                    "display_sans": entry.display_sans(),
+                    "metadata": resource,
+                    "certificate_base64": encoded_cert,
+                })));
             }
 
             response::error(
@@ -62,18 +170,36 @@ pub async fn get_certificate(
                 "Certificate file is missing despite being marked as ready.",
             )
         }
-        Some(DomainStatus::Acquiring) => (
+        (Some(DomainStatus::Queued), _) => (
+            StatusCode::ACCEPTED,
+            Json(json!({"status": "Accepted", "message": "Certificate acquisition is queued."})),
+        )
+            .into_response(),
+        (Some(DomainStatus::Acquiring), _) => (
             StatusCode::ACCEPTED,
             Json(
                 json!({"status": "Accepted", "message": "Certificate acquisition is in progress."}),
             ),
         )
             .into_response(),
-        Some(DomainStatus::Failed(reason)) => response::error(
+        (
+            Some(DomainStatus::Failed {
+                reason,
+                attempts,
+                next_retry_at,
+            }),
+            _,
+        ) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Certificate acquisition failed: {}", reason),
-        ),
-        None => response::error(
+            Json(json!({
+                "status": "Error",
+                "message": format!("Certificate acquisition failed: {}", reason),
+                "attempts": attempts,
+                "next_retry_at": next_retry_at,
+            })),
+        )
+            .into_response(),
+        _ => response::error(
             StatusCode::NOT_FOUND,
             "Certificate for this domain is not managed or found.",
         ),
@@ -85,109 +211,403 @@ pub async fn get_certificate_key(
     Path(domain): Path<String>,
     Query(query): Query<CertQuery>,
 ) -> Response {
-    if !matches!(
-        state.domains.read().get(domain.trim()),
-        Some(DomainStatus::Ready)
-    ) {
-        return response::error(
-            StatusCode::NOT_FOUND,
-            "Certificate is not ready or does not exist.",
-        );
-    }
+    let entry = state.certificates.read().get(domain.trim()).cloned();
+    let entry = match (state.domains.read().get(domain.trim()), entry) {
+        (Some(DomainStatus::Ready), Some(entry)) => entry,
+        _ => {
+            return response::error(
+                StatusCode::NOT_FOUND,
+                "Certificate is not ready or does not exist.",
+            );
+        }
+    };
 
     let cert_dir = state.config.dir_path.join(".lego/certificates");
-    let domain_name = domain.trim();
-
-    let paths_to_try: Vec<PathBuf> = if query.wildcard {
-        vec![cert_dir.join(format!("_.{}.key", domain_name))]
-    } else {
-        vec![
-            cert_dir.join(format!("_.{}.key", domain_name)),
-            cert_dir.join(format!("{}.key", domain_name)),
-        ]
+    let resource = match issued_resource(&state, &entry, query.wildcard) {
+        Ok(resource) => resource,
+        Err((status, message)) => return response::error(status, message),
     };
-
-    for path in paths_to_try {
-        if let Ok(content_bytes) = fs::read(&path).await {
-            let encoded_key = STANDARD.encode(&content_bytes);
-            return response::success(Some(json!({ "key_base64": encoded_key })));
-        }
+    if let Ok(content_bytes) = fs::read(resource.path(&cert_dir, "key")).await {
+        let encoded_key = STANDARD.encode(&content_bytes);
+        return response::success(Some(json!({ "key_base64": encoded_key })));
     }
 
     response::error(StatusCode::INTERNAL_SERVER_ERROR, "Key file is missing.")
 }
 
+/// The lego resource recorded for `id`. With `wildcard`, the certificate must
+/// also cover a wildcard name.
+fn issued_resource(
+    state: &AppState,
+    entry: &CertificateEntry,
+    wildcard: bool,
+) -> Result<CertificateMetadata, (StatusCode, &'static str)> {
+    let Some(resource) = state.metadata.read().get(&entry.id).cloned() else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Certificate file is missing despite being marked as ready.",
+        ));
+    };
+    if wildcard && !resource.has_wildcard(entry) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "This certificate does not cover a wildcard name.",
+        ));
+    }
+    Ok(resource)
+}
+
+/// Either `domain` (wildcard + apex, as in `[[domains]]`) or `id` with `sans`.
 #[derive(Deserialize)]
 pub struct CreateCertRequest {
-    pub domain: String,
+    pub domain: Option<String>,
+    pub id: Option<String>,
+    pub sans: Option<Vec<SanEntry>>,
     pub dns: String,
 }
 
-pub async fn create_certificate(
-    State(state): State<AppState>,
-    Json(payload): Json<CreateCertRequest>,
-) -> Response {
-    let domain = payload.domain.trim();
-    let dns_provider = payload.dns.trim();
+impl CreateCertRequest {
+    fn to_entry(&self, allow_ip: bool) -> Result<CertificateEntry, String> {
+        let mut entry = self.to_raw_entry()?;
+        entry.validate(allow_ip)?;
+        Ok(entry)
+    }
 
-    {
-        let domains = state.domains.read();
-        if let Some(status) = domains.get(domain) {
-            match status {
-                DomainStatus::Acquiring => {
-                    return response::error(
-                        StatusCode::CONFLICT,
-                        "Certificate acquisition for this domain is already in progress.",
-                    );
-                }
-                DomainStatus::Ready => {
-                    return response::error(
-                        StatusCode::BAD_REQUEST,
-                        "Certificate for this domain already exists.",
-                    );
+    fn to_raw_entry(&self) -> Result<CertificateEntry, &'static str> {
+        match (&self.sans, &self.domain) {
+            (Some(sans), _) => {
+                let id = self
+                    .id
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .ok_or("'id' is required when 'sans' is given.")?;
+                let entry = CertificateEntry::new(id.to_string(), sans.clone(), self.dns.clone());
+                if entry.sans.is_empty() || entry.sans.iter().any(String::is_empty) {
+                    return Err("'sans' must contain at least one name and no empty names.");
                 }
-                DomainStatus::Failed(_) => {}
+                Ok(entry)
             }
+            (None, Some(domain)) if !domain.trim().is_empty() => {
+                Ok(CertificateEntry::from(&DomainEntry {
+                    name: domain.clone(),
+                    dns_provider: self.dns.clone(),
+                    retry: Default::default(),
+                    maintenance_window: None,
+                    renew_before: None,
+                    disabled: None,
+                }))
+            }
+            _ => Err("Either 'domain' or 'id' with 'sans' is required."),
         }
+    }
+}
 
-        let mut is_acquiring_lock = state.is_acquiring.write();
-        if *is_acquiring_lock {
+#[derive(Deserialize)]
+pub struct CreateCertQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub async fn create_certificate(
+    State(state): State<AppState>,
+    Query(query): Query<CreateCertQuery>,
+    Json(payload): Json<CreateCertRequest>,
+) -> Response {
+    let entry = match payload.to_entry(state.config.allow_ip_certificates) {
+        Ok(entry) => entry,
+        Err(message) => return response::error(StatusCode::BAD_REQUEST, message),
+    };
+    // Asking for a certificate that is already managed is fine, but only as
+    // it is: the existing entry keeps its settings and stays the only one.
+    let existing = state.certificates.read().get(&entry.id).cloned();
+    let entry = match existing {
+        Some(existing) if existing.same_certificate(&entry) => existing,
+        Some(_) => {
             return response::error(
-                StatusCode::SERVICE_UNAVAILABLE,
-                "Another certificate acquisition is currently in progress. Please try again later.",
+                StatusCode::CONFLICT,
+                format!(
+                    "Certificate '{}' is already configured with other SANs or DNS providers.",
+                    entry.id
+                ),
             );
         }
-        *is_acquiring_lock = true;
-        log(LogLevel::Debug, "Global acquisition lock acquired.");
-    }
-
-    let dns_config_path = state
-        .config
-        .dir_path
-        .join(format!("{}.dns.toml", dns_provider));
-    if !tokio::fs::metadata(dns_config_path).await.is_ok() {
-        *state.is_acquiring.write() = false;
-        log(
-            LogLevel::Debug,
-            "DNS config not found, releasing global acquisition lock.",
-        );
+        None => entry,
+    };
+    let domain = entry.id.as_str();
+
+    let conflict = state
+        .certificates
+        .read()
+        .values()
+        .filter(|other| other.id != entry.id)
+        .find_map(|other| entry.conflict_with(other));
+    if let Some(conflict) = conflict {
+        return response::error(StatusCode::CONFLICT, format!("{}.", conflict));
+    }
+
+    let mut missing_provider = false;
+    for provider in entry.providers() {
+        let dns_config_path = state.config.dir_path.join(format!("{}.dns.toml", provider));
+        missing_provider |= tokio::fs::metadata(dns_config_path).await.is_err();
+    }
+    if missing_provider {
         return response::error(
             StatusCode::BAD_REQUEST,
             "Specified DNS provider configuration not found.",
         );
     }
+    if let Err(e) = lego::check_routing(&entry, &state.config).await {
+        return response::error(StatusCode::BAD_REQUEST, e);
+    }
+
+    // A dry run of an issued certificate plans its renewal.
+    if query.dry_run {
+        return match acme::plan(&state.config, &entry).await {
+            Ok(plan) => response::success(Some(json!(plan))),
+            Err(e) => response::error(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+    }
 
-    tokio::spawn(acme::acquire_or_renew_certificate(
-        state.clone(),
-        domain.to_string(),
-        dns_provider.to_string(),
-        true,
-        CommandType::Run,
-    ));
+    if let Some(DomainStatus::Ready) = state.domains.read().get(domain) {
+        return response::error(
+            StatusCode::BAD_REQUEST,
+            "Certificate for this domain already exists.",
+        );
+    }
+
+    if let Some(hold) = tasks::disabled_hold(&state, domain) {
+        return response::error(
+            StatusCode::CONFLICT,
+            format!("{}.", hold.describe("Certificate is disabled")),
+        );
+    }
+
+    let enqueued = tasks::enqueue(&state, entry, JobKind::Acquire, Trigger::Api, true, None);
+    let job_id = enqueued.job_id().to_string();
+    // Asking again is a manual retry: skip whatever backoff is left.
+    if let Enqueued::Existing(_) = enqueued {
+        tasks::expedite(&state, &job_id);
+    }
+    let (queue_position, eta_secs) = queue_estimate(&state, &job_id);
+    let message = match enqueued {
+        Enqueued::New(_) => "Certificate acquisition queued.",
+        Enqueued::Existing(_) => "A job for this certificate is already queued or running.",
+    };
 
     (
         StatusCode::ACCEPTED,
-        Json(json!({"status": "Accepted", "message": "Certificate acquisition process started."})),
+        Json(json!({
+            "status": "Accepted",
+            "message": message,
+            "job_id": job_id,
+            "deduplicated": matches!(enqueued, Enqueued::Existing(_)),
+            "queue_position": queue_position,
+            "eta_secs": eta_secs,
+        })),
     )
         .into_response()
 }
+
+/// The 1-based queue position of a pending job and the seconds until it is
+/// expected to start. Both are `None` once the job has left the queue.
+fn queue_estimate(state: &AppState, job_id: &str) -> (Option<usize>, Option<i64>) {
+    let (ahead, running) = {
+        let queue = state.queue.read();
+        let Some(ahead) = queue.jobs_ahead(job_id) else {
+            return (None, None);
+        };
+        (ahead, queue.running().len())
+    };
+    let (average, delayed_until) = {
+        let jobs = state.jobs.read();
+        let delayed_until = jobs.get(job_id).and_then(|job| job.delayed_until);
+        (jobs.average_duration(), delayed_until)
+    };
+    let parallel = state.config.max_concurrent_jobs;
+    let mut eta = queue::eta_secs(running + ahead, average, parallel);
+    // A job held back by a CA rate limit starts no earlier than it clears.
+    if let Some(until) = delayed_until {
+        eta = eta.max((until - chrono::Utc::now()).num_seconds());
+    }
+    (Some(ahead + 1), Some(eta))
+}
+
+pub async fn get_queue(State(state): State<AppState>) -> Response {
+    let queue = state.queue.read();
+    let describe = |job: &queue::QueuedJob| {
+        json!({
+            "job_id": job.job_id,
+            "certificate_id": job.entry.id,
+            "kind": job.kind,
+            "deadline": job.deadline,
+            "enqueued_at": job.enqueued_at,
+        })
+    };
+    let running: Vec<_> = queue.running().iter().map(describe).collect();
+    let pending: Vec<_> = queue.pending().into_iter().map(describe).collect();
+    response::success(Some(json!({ "running": running, "pending": pending })))
+}
+
+pub async fn get_job(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let Some(mut job) = state.jobs.read().get(&id).map(|job| json!(job)) else {
+        return response::error(StatusCode::NOT_FOUND, "Job not found.");
+    };
+    if let (Some(position), Some(eta)) = queue_estimate(&state, &id) {
+        job["queue_position"] = json!(position);
+        job["eta_secs"] = json!(eta);
+    }
+    response::success(Some(job))
+}
+
+pub async fn get_job_log(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.jobs.read().get(&id) {
+        Some(job) => response::success(Some(json!({ "id": job.id, "log": job.log }))),
+        None => response::error(StatusCode::NOT_FOUND, "Job not found."),
+    }
+}
+
+pub async fn cancel_job(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    // A job that has not started yet is simply taken off the queue.
+    let removed = state.queue.write().remove(&id);
+    if let Some(job) = removed {
+        state.jobs.write().finish(
+            &id,
+            JobStatus::Cancelled,
+            Some("Cancelled before it started.".to_string()),
+        );
+        let entry = state
+            .jobs
+            .read()
+            .get(&id)
+            .map(|record| HistoryEntry::new(record, job.attempt, None));
+        if let Some(entry) = entry {
+            state.history.append(entry).await;
+        }
+        let mut domains = state.domains.write();
+        if let Some(DomainStatus::Queued) = domains.get(&job.entry.id) {
+            domains.remove(&job.entry.id);
+        }
+        return response::success(Some(json!({ "job_id": id, "status": "cancelled" })));
+    }
+
+    match state.jobs.read().cancel(&id) {
+        Some(Ok(())) => (
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "status": "Accepted",
+                "message": "Cancellation requested.",
+                "job_id": id,
+            })),
+        )
+            .into_response(),
+        Some(Err(status)) => response::error(
+            StatusCode::CONFLICT,
+            format!(
+                "Job has already finished with status '{}'.",
+                format!("{:?}", status).to_lowercase()
+            ),
+        ),
+        None => response::error(StatusCode::NOT_FOUND, "Job not found."),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    #[serde(flatten)]
+    filter: HistoryFilter,
+    limit: Option<usize>,
+}
+
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(100).min(1000);
+    match state.history.query(query.filter, limit).await {
+        Ok(entries) => response::success(Some(json!({ "entries": entries }))),
+        Err(e) => response::error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read history: {}", e),
+        ),
+    }
+}
+
+/// Reads an optional `{"reason": .., "until": ..}` body.
+fn hold_from(body: Option<Json<Hold>>) -> Result<Hold, &'static str> {
+    let hold = body.map(|Json(hold)| hold).unwrap_or_default();
+    if hold.until.is_some_and(|until| until <= Utc::now()) {
+        return Err("'until' must be in the future.");
+    }
+    Ok(hold)
+}
+
+pub async fn pause_scheduler(State(state): State<AppState>, body: Option<Json<Hold>>) -> Response {
+    let hold = match hold_from(body) {
+        Ok(hold) => hold,
+        Err(message) => return response::error(StatusCode::BAD_REQUEST, message),
+    };
+    log(
+        LogLevel::Warn,
+        &format!("{}.", hold.describe("Scheduler paused")),
+    );
+    state.holds.write().pause(hold.clone());
+    response::success(Some(json!({ "paused": hold })))
+}
+
+pub async fn resume_scheduler(State(state): State<AppState>) -> Response {
+    let resumed = state.holds.write().resume();
+    if state.holds.read().config_paused(Utc::now()) {
+        return response::error(
+            StatusCode::CONFLICT,
+            "The scheduler is paused in config.toml.",
+        );
+    }
+    if !resumed {
+        return response::error(StatusCode::CONFLICT, "The scheduler is not paused.");
+    }
+    log(LogLevel::Info, "Scheduler resumed.");
+    state.queue_notify.notify_one();
+    response::success(Some(json!({ "paused": null })))
+}
+
+pub async fn disable_certificate(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<Hold>>,
+) -> Response {
+    let id = id.trim();
+    if !state.certificates.read().contains_key(id) {
+        return response::error(StatusCode::NOT_FOUND, "Certificate not found.");
+    }
+    let hold = match hold_from(body) {
+        Ok(hold) => hold,
+        Err(message) => return response::error(StatusCode::BAD_REQUEST, message),
+    };
+    log(
+        LogLevel::Warn,
+        &format!(
+            "{}.",
+            hold.describe(&format!("Certificate '{}' disabled", id))
+        ),
+    );
+    state.holds.write().disable(id, hold.clone());
+    response::success(Some(json!({ "id": id, "disabled": hold })))
+}
+
+pub async fn enable_certificate(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let id = id.trim();
+    let enabled = state.holds.write().enable(id);
+    if tasks::disabled_hold(&state, id).is_some() {
+        return response::error(
+            StatusCode::CONFLICT,
+            "The certificate is disabled in config.toml.",
+        );
+    }
+    if !enabled {
+        return response::error(StatusCode::CONFLICT, "The certificate is not disabled.");
+    }
+    log(LogLevel::Info, &format!("Certificate '{}' enabled.", id));
+    state.queue_notify.notify_one();
+    response::success(Some(json!({ "id": id, "disabled": null })))
+}
@@ -0,0 +1,249 @@
+/* src/history.rs */
+
+use crate::jobs::{JobKind, JobRecord, JobStatus, Trigger};
+use chrono::{DateTime, Utc};
+use fancy_log::{LogLevel, log};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// What a failed run ran into, judged from its error and lego's output.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// The run could not be prepared, e.g. a missing provider config.
+    Config,
+    RateLimited,
+    Dns,
+    Authorization,
+    Timeout,
+    /// lego failed for another reason.
+    Lego,
+}
+
+impl ErrorClass {
+    pub fn classify(error: &str, log: &[String]) -> Self {
+        let error = error.to_ascii_lowercase();
+        if error.contains("timed out") {
+            return ErrorClass::Timeout;
+        }
+        if !lego_ran(Some(&error), log) {
+            return ErrorClass::Config;
+        }
+        let output = log.join("\n").to_ascii_lowercase();
+        if output.contains("ratelimited") || output.contains("too many") {
+            ErrorClass::RateLimited
+        } else if ["nxdomain", "propagation", "txt record", "dns problem"]
+            .iter()
+            .any(|s| output.contains(s))
+        {
+            ErrorClass::Dns
+        } else if ["unauthorized", "caa", "403"]
+            .iter()
+            .any(|s| output.contains(s))
+        {
+            ErrorClass::Authorization
+        } else {
+            ErrorClass::Lego
+        }
+    }
+}
+
+/// Whether lego was started, judging from a job's error and log: every run
+/// that reaches lego logs its command line first, and fails with "Failed to
+/// start" if the program cannot be started.
+pub fn lego_ran(error: Option<&str>, log: &[String]) -> bool {
+    log.iter().any(|line| line.starts_with("$ "))
+        && !error.is_some_and(|e| e.to_ascii_lowercase().starts_with("failed to start"))
+}
+
+/// One finished acquisition or renewal, as stored in `history.jsonl`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub job_id: String,
+    pub certificate_id: String,
+    pub kind: JobKind,
+    pub trigger: Trigger,
+    pub outcome: JobStatus,
+    pub attempt: u32,
+    pub queued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_secs: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<ErrorClass>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// SHA-256 of the issued certificate, for successful runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(job: &JobRecord, attempt: u32, fingerprint: Option<String>) -> Self {
+        let finished_at = job.finished_at.unwrap_or_else(Utc::now);
+        let error_class = match job.status {
+            JobStatus::Failed => Some(ErrorClass::classify(
+                job.error.as_deref().unwrap_or_default(),
+                &job.log,
+            )),
+            _ => None,
+        };
+        HistoryEntry {
+            job_id: job.id.clone(),
+            certificate_id: job.certificate_id.clone(),
+            kind: job.kind,
+            trigger: job.trigger,
+            outcome: job.status,
+            attempt,
+            queued_at: job.queued_at,
+            started_at: job.started_at,
+            finished_at,
+            duration_secs: job
+                .started_at
+                .map(|start| (finished_at - start).num_milliseconds() as f64 / 1000.0),
+            error_class,
+            error: job.error.clone(),
+            fingerprint,
+        }
+    }
+}
+
+/// Filters for `GET /v1/history`; every field that is set must match.
+#[derive(Deserialize, Default)]
+pub struct HistoryFilter {
+    pub certificate: Option<String>,
+    pub kind: Option<JobKind>,
+    pub trigger: Option<Trigger>,
+    pub outcome: Option<JobStatus>,
+    pub error_class: Option<ErrorClass>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.certificate
+            .as_ref()
+            .is_none_or(|id| *id == entry.certificate_id)
+            && self.kind.is_none_or(|kind| kind == entry.kind)
+            && self.trigger.is_none_or(|trigger| trigger == entry.trigger)
+            && self.outcome.is_none_or(|outcome| outcome == entry.outcome)
+            && self
+                .error_class
+                .is_none_or(|class| Some(class) == entry.error_class)
+            && self.since.is_none_or(|since| entry.finished_at >= since)
+            && self.until.is_none_or(|until| entry.finished_at < until)
+    }
+}
+
+/// Every finished run, appended one JSON object per line to `history.jsonl`.
+/// Once the file reaches `max_bytes` it is moved to `history.jsonl.1`,
+/// replacing the previous one, so at most about twice that is kept. The file
+/// is read and written on blocking threads.
+#[derive(Clone)]
+pub struct History {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Keeps an append from racing a rotation.
+    appending: Arc<Mutex<()>>,
+}
+
+impl History {
+    pub fn new(dir_path: &Path, max_bytes: u64) -> Self {
+        Self {
+            path: dir_path.join(HISTORY_FILE),
+            max_bytes,
+            appending: Arc::default(),
+        }
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        self.path.with_extension("jsonl.1")
+    }
+
+    pub async fn append(&self, entry: HistoryEntry) {
+        let history = self.clone();
+        let result = tokio::task::spawn_blocking(move || history.write_line(&entry))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result);
+        if let Err(e) = result {
+            log(
+                LogLevel::Error,
+                &format!("Failed to append to history file {:?}: {}", self.path, e),
+            );
+        }
+    }
+
+    fn write_line(&self, entry: &HistoryEntry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let _appending = self.appending.lock();
+        let full = std::fs::metadata(&self.path).is_ok_and(|m| m.len() >= self.max_bytes);
+        if full {
+            std::fs::rename(&self.path, self.rotated_path()).map_err(|e| e.to_string())?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Matching entries, newest first, at most `limit` of them. Lines that
+    /// do not parse are skipped.
+    pub async fn query(
+        &self,
+        filter: HistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<HistoryEntry>, String> {
+        let history = self.clone();
+        tokio::task::spawn_blocking(move || history.read(&filter, limit))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    fn read(&self, filter: &HistoryFilter, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+        let mut entries = Vec::new();
+        for path in [self.path.clone(), self.rotated_path()] {
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            let remaining = limit - entries.len();
+            entries.extend(
+                content
+                    .lines()
+                    .rev()
+                    .filter_map(|line| serde_json::from_str::<HistoryEntry>(line).ok())
+                    .filter(|entry| filter.matches(entry))
+                    .take(remaining),
+            );
+            if entries.len() >= limit {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The SHA-256 fingerprint of the first certificate in a PEM file, as
+/// colon-separated hex like `openssl x509 -fingerprint -sha256` prints it.
+pub fn fingerprint(pem_data: &[u8]) -> Option<String> {
+    let pem = ::pem::parse(pem_data).ok()?;
+    let digest = Sha256::digest(pem.contents());
+    Some(
+        digest
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
@@ -0,0 +1,347 @@
+/* src/hook.rs */
+
+use crate::{
+    config::{self, DnsProviderConfig, HookTarget, ProviderType, WebhookRequest},
+    secrets, template, zone,
+};
+use fancy_log::{LogLevel, log};
+use serde::{Deserialize, Serialize};
+use serde_json_path::JsonPath;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+// Environment handed to lego so that its `exec` provider can call back into us.
+pub const HOOK_CONFIG_ENV: &str = "LAZY_ACME_HOOK_CONFIG";
+// JSON map from a SAN (without `*.`) to the provider config that serves it.
+pub const HOOK_ROUTES_ENV: &str = "LAZY_ACME_HOOK_ROUTES";
+
+const DEFAULT_TTL: i64 = 120;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+const EXEC_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookAction {
+    Present,
+    Cleanup,
+}
+
+impl HookAction {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "present" => Some(HookAction::Present),
+            "cleanup" => Some(HookAction::Cleanup),
+            _ => None,
+        }
+    }
+}
+
+/// The JSON document written to the hook executable's stdin.
+#[derive(Serialize, Debug)]
+pub struct HookRequest {
+    pub action: HookAction,
+    pub fqdn: String,
+    pub value: String,
+    pub zone: String,
+    pub record_name: String,
+    pub ttl: i64,
+}
+
+/// The JSON document the hook executable must print on stdout.
+#[derive(Deserialize, Debug)]
+pub struct HookResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+pub struct HookInvocation {
+    pub action: HookAction,
+    pub fqdn: String,
+    pub value: String,
+}
+
+/// lego's `exec` provider calls `$EXEC_PATH present|cleanup <fqdn> <value>`.
+/// The process is a hook whenever lego set up our environment; arguments in
+/// any other shape are an error rather than a reason to start the daemon.
+pub fn parse_invocation(args: &[String]) -> Option<Result<HookInvocation, String>> {
+    std::env::var_os(HOOK_CONFIG_ENV)?;
+    let invocation = match args {
+        [_, action, fqdn, value] => HookAction::parse(action).map(|action| HookInvocation {
+            action,
+            fqdn: fqdn.clone(),
+            value: value.clone(),
+        }),
+        _ => None,
+    };
+    Some(invocation.ok_or_else(|| {
+        format!(
+            "{} is set, so this is a DNS hook call, but its arguments {:?} are not \
+             'present|cleanup <fqdn> <value>' (EXEC_MODE=RAW is not supported)",
+            HOOK_CONFIG_ENV,
+            args.get(1..).unwrap_or_default()
+        )
+    }))
+}
+
+pub async fn run(
+    invocation: HookInvocation,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let fqdn = invocation.fqdn.trim_end_matches('.').to_string();
+    let config_path = match routed_config_path(&fqdn)? {
+        Some(path) => path,
+        None => PathBuf::from(
+            std::env::var(HOOK_CONFIG_ENV)
+                .map_err(|_| format!("{} is not set", HOOK_CONFIG_ENV))?,
+        ),
+    };
+    let mut provider_config = config::load_dns_provider_config(&config_path).await?;
+    secrets::resolve(&secrets::provider_name(&config_path), &mut provider_config).await?;
+
+    let zone = match provider_config.var_str("zone") {
+        Some(zone) => zone,
+        None => zone::find_zone(&fqdn).await?,
+    };
+    let request = HookRequest {
+        action: invocation.action,
+        record_name: zone::relative_name(&fqdn, &zone),
+        fqdn,
+        value: invocation.value,
+        zone,
+        ttl: provider_ttl(&provider_config),
+    };
+
+    let target = match request.action {
+        HookAction::Present => provider_config.present.as_ref(),
+        HookAction::Cleanup => provider_config.cleanup.as_ref(),
+    }
+    .ok_or_else(|| format!("Provider config has no '{:?}' hook", request.action))?;
+
+    match (provider_config.provider_type, target) {
+        (ProviderType::Exec, HookTarget::Program(program)) => {
+            run_exec_hook(program, &request).await
+        }
+        (ProviderType::Webhook, HookTarget::Http(webhook)) => {
+            run_webhook_hook(&provider_config, webhook, &request).await
+        }
+        (provider_type, _) => Err(format!(
+            "'{:?}' hook does not match provider type {:?}",
+            request.action, provider_type
+        )
+        .into()),
+    }
+}
+
+/// The provider config routed to `fqdn`, or `None` when the certificate does
+/// not route SANs. A routed certificate must have a route for every challenge
+/// name; falling back to the default provider would publish it in the wrong
+/// place.
+fn routed_config_path(
+    fqdn: &str,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let Ok(routes) = std::env::var(HOOK_ROUTES_ENV) else {
+        return Ok(None);
+    };
+    let mut routes: HashMap<String, PathBuf> = serde_json::from_str(&routes)?;
+    let name = fqdn.to_ascii_lowercase();
+    let name = name
+        .strip_prefix(&format!("{}.", zone::CHALLENGE_LABEL))
+        .unwrap_or(&name);
+    match routes.remove(name) {
+        Some(path) => Ok(Some(path)),
+        None => Err(format!(
+            "No DNS provider is routed for '{}'; if its challenge record is a CNAME, \
+             lego followed it to a name outside the certificate",
+            fqdn
+        )
+        .into()),
+    }
+}
+
+fn provider_ttl(provider_config: &DnsProviderConfig) -> i64 {
+    provider_config
+        .var_str("ttl")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TTL)
+}
+
+async fn run_exec_hook(
+    program: &str,
+    request: &HookRequest,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log(
+        LogLevel::Debug,
+        &format!(
+            "Running {:?} hook '{}' for {}",
+            request.action, program, request.fqdn
+        ),
+    );
+
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start hook '{}': {}", program, e))?;
+
+    let mut stdin = child.stdin.take().expect("Failed to open stdin");
+    stdin.write_all(&serde_json::to_vec(request)?).await?;
+    drop(stdin);
+
+    let output = tokio::time::timeout(EXEC_HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            format!(
+                "Hook '{}' did not finish within {}s",
+                program,
+                EXEC_HOOK_TIMEOUT.as_secs()
+            )
+        })??;
+    let response: HookResponse = serde_json::from_slice(&output.stdout).map_err(|e| {
+        format!(
+            "Hook '{}' did not print a valid JSON response ({}): {}",
+            program,
+            e,
+            String::from_utf8_lossy(&output.stdout).trim()
+        )
+    })?;
+
+    if !output.status.success() || !response.success {
+        return Err(format!(
+            "Hook '{}' failed ({}): {}",
+            program,
+            output.status,
+            response.message.unwrap_or_default()
+        )
+        .into());
+    }
+
+    if let Some(message) = response.message {
+        log(LogLevel::Info, &message);
+    }
+    Ok(())
+}
+
+async fn run_webhook_hook(
+    provider_config: &DnsProviderConfig,
+    webhook: &WebhookRequest,
+    request: &HookRequest,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let lookup = |key: &str| -> Option<String> {
+        match key.to_ascii_uppercase().as_str() {
+            "ACTION" => Some(format!("{:?}", request.action).to_lowercase()),
+            "FQDN" => Some(request.fqdn.clone()),
+            "VALUE" => Some(request.value.clone()),
+            "ZONE" => Some(request.zone.clone()),
+            "RECORD_NAME" => Some(request.record_name.clone()),
+            "TTL" => Some(request.ttl.to_string()),
+            _ => provider_config.var_str(key),
+        }
+    };
+
+    let method = reqwest::Method::from_bytes(webhook.method.to_uppercase().as_bytes())?;
+    let url = template::render(&webhook.url, lookup)?;
+    log(
+        LogLevel::Debug,
+        &format!("Calling {:?} webhook: {} {}", request.action, method, url),
+    );
+
+    let client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()?;
+    let mut builder = client.request(method, &url);
+    for (name, value) in &webhook.headers {
+        builder = builder.header(name, template::render(value, lookup)?);
+    }
+    if let Some(body) = &webhook.body {
+        builder = builder
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(render_json(body, lookup)?);
+    }
+
+    let response = builder.send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+
+    let status_ok = if webhook.expect_status.is_empty() {
+        status.is_success()
+    } else {
+        webhook.expect_status.contains(&status.as_u16())
+    };
+    if !status_ok {
+        return Err(format!("Webhook {} returned {}: {}", url, status, text.trim()).into());
+    }
+
+    if !webhook.assertions.is_empty() {
+        let body: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Webhook {} did not return JSON: {}", url, e))?;
+        for assertion in &webhook.assertions {
+            let path = JsonPath::parse(&assertion.path)
+                .map_err(|e| format!("Invalid JSONPath '{}': {}", assertion.path, e))?;
+            let found = path.query(&body).all();
+            let passed = match &assertion.equals {
+                Some(expected) => found.contains(&expected),
+                None => !found.is_empty(),
+            };
+            if !passed {
+                return Err(format!(
+                    "Webhook {} response failed assertion on '{}': {}",
+                    url,
+                    assertion.path,
+                    text.trim()
+                )
+                .into());
+            }
+        }
+    }
+
+    log(
+        LogLevel::Info,
+        &format!(
+            "{:?} webhook succeeded for {}",
+            request.action, request.fqdn
+        ),
+    );
+    Ok(())
+}
+
+/// Renders a JSON body template. Values are JSON-escaped, so they can only
+/// ever fill the string or number they are placed in.
+fn render_json(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, template::TemplateError> {
+    template::render(template, |key| {
+        lookup(key).map(|value| {
+            let quoted = serde_json::Value::String(value).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_values_are_json_escaped() {
+        let body = r#"{"name": "{{FQDN}}", "content": "{{VALUE}}", "ttl": {{TTL}}}"#;
+        let value = r#"a\", "admin": true, "x": "
+"#;
+        let lookup = |key: &str| match key {
+            "FQDN" => Some("_acme-challenge.example.com".to_string()),
+            "VALUE" => Some(value.to_string()),
+            "TTL" => Some("120".to_string()),
+            _ => None,
+        };
+        let rendered = render_json(body, lookup).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(parsed["content"], value);
+        assert_eq!(parsed["ttl"], 120);
+        assert!(parsed.get("admin").is_none());
+    }
+}
@@ -16,6 +16,23 @@ const DEFAULT_CONFIG_TOML: &str = r#"
 # [[domains]]
 # name = "another.dev"
 # dns_provider = "cloudflare_zerossl"
+
+# A `[[domains]]` entry issues `*.name` and `name`. For any other set of names,
+# use a `[[certificates]]` entry with a stable id. Use `{{SANS}}` in the
+# provider's command to pass the names to lego.
+
+# [[certificates]]
+# id = "web"
+# sans = ["*.example.com", "example.com", "status.example.net"]
+# dns_provider = "cloudflare"
+
+# A SAN may name its own provider. All providers of such a certificate must be
+# of type "exec" or "webhook".
+
+# [[certificates]]
+# id = "mixed"
+# sans = ["example.com", { name = "example.org", dns_provider = "other_vendor" }]
+# dns_provider = "internal_dns"
 "#;
 
 const DEFAULT_CLOUDFLARE_DNS_TOML: &str = r#"
@@ -23,11 +40,14 @@ const DEFAULT_CLOUDFLARE_DNS_TOML: &str = r#"
 # You can copy this file to create configs for different CAs,
 # e.g., 'cloudflare_zerossl.dns.toml'.
 
-# The command to run when initially acquiring a certificate.
-cmd = "CLOUDFLARE_DNS_API_TOKEN={{API_KEY}} lego --email {{EMAIL}} --server {{CA}} --dns cloudflare -d '*.{{DOMAIN}}' -d {{DOMAIN}} run"
+# The lego DNS provider to use (see `lego dnshelp`).
+dns = "cloudflare"
 
-# The command to run when renewing a certificate.
-renew = "CLOUDFLARE_DNS_API_TOKEN={{API_KEY}} lego --email {{EMAIL}} --server {{CA}} --dns cloudflare -d '*.{{DOMAIN}}' -d {{DOMAIN}} renew --days 30"
+# Extra arguments for lego, placed before `run` / `renew`.
+# args = ["--key-type", "ec256"]
+# run_args = []
+# renew_args defaults to ["--days", "{{RENEW_DAYS}}"], derived from renew_before.
+# renew_args = ["--days", "{{RENEW_DAYS}}"]
 
 # --- Your Credentials ---
 api_key = "YOUR_CLOUDFLARE_API_TOKEN_HERE"
@@ -39,6 +59,17 @@ email = "your-email@example.com"
 # ZeroSSL (requires EAB): https://acme.zerossl.com/v2/DV90
 # Buypass Go SSL: https://api.buypass.com/acme/directory
 ca = "https://acme-v02.api.letsencrypt.org/directory"
+
+# Legacy shell templates are still available, but must be chosen explicitly,
+# since every value ends up inside a shell command line:
+#
+# type = "shell"
+# cmd = "CLOUDFLARE_DNS_API_TOKEN={{API_KEY}} lego --email {{EMAIL}} --server {{CA}} --dns cloudflare {{SANS}} run"
+# renew = "CLOUDFLARE_DNS_API_TOKEN={{API_KEY}} lego --email {{EMAIL}} --server {{CA}} --dns cloudflare {{SANS}} renew --days {{RENEW_DAYS}}"
+
+# Environment passed to lego. Values may use {{PLACEHOLDERS}}.
+[env]
+CLOUDFLARE_DNS_API_TOKEN = "{{API_KEY}}"
 "#;
 
 pub async fn initialize_app(config: &AppConfig) -> Result<bool, std::io::Error> {
@@ -0,0 +1,260 @@
+/* src/jobs.rs */
+
+use crate::redact;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+const MAX_JOBS: usize = 100;
+const MAX_LOG_LINES: usize = 5000;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Acquire,
+    Renew,
+}
+
+/// What queued a job.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// The check for missing certificates at startup.
+    Startup,
+    /// The renewal scheduler or one of its rescans.
+    #[default]
+    Schedule,
+    Api,
+    /// A failed job's backoff.
+    Retry,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// One acquisition or renewal run, with the (redacted) output lego produced.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub certificate_id: String,
+    pub kind: JobKind,
+    pub trigger: Trigger,
+    pub status: JobStatus,
+    pub queued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    /// Why a queued job is being held back, and until when if that is known.
+    pub delay_reason: Option<String>,
+    pub delayed_until: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub log: Vec<String>,
+    #[serde(skip)]
+    pub cancel: Arc<Notify>,
+    #[serde(skip)]
+    pub done: Arc<Notify>,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Recent jobs, oldest evicted first.
+#[derive(Default)]
+pub struct JobStore {
+    jobs: HashMap<String, JobRecord>,
+    order: VecDeque<String>,
+    next_seq: u64,
+}
+
+impl JobStore {
+    pub fn create(&mut self, certificate_id: &str, kind: JobKind, trigger: Trigger) -> String {
+        self.next_seq += 1;
+        let now = Utc::now();
+        let id = format!("{}-{}", now.format("%Y%m%d%H%M%S"), self.next_seq);
+        self.jobs.insert(
+            id.clone(),
+            JobRecord {
+                id: id.clone(),
+                certificate_id: certificate_id.to_string(),
+                kind,
+                trigger,
+                status: JobStatus::Queued,
+                queued_at: now,
+                started_at: None,
+                finished_at: None,
+                error: None,
+                delay_reason: None,
+                delayed_until: None,
+                log: Vec::new(),
+                cancel: Arc::new(Notify::new()),
+                done: Arc::new(Notify::new()),
+            },
+        );
+        self.order.push_back(id.clone());
+        // Queued and running jobs are never evicted.
+        while self.order.len() > MAX_JOBS {
+            let Some(pos) = self.order.iter().position(|old| {
+                self.jobs
+                    .get(old)
+                    .is_none_or(|job| job.status.is_finished())
+            }) else {
+                break;
+            };
+            if let Some(old) = self.order.remove(pos) {
+                self.jobs.remove(&old);
+            }
+        }
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<&JobRecord> {
+        self.jobs.get(id)
+    }
+
+    pub fn append_log(&mut self, id: &str, line: String) {
+        if let Some(job) = self.jobs.get_mut(id)
+            && job.log.len() < MAX_LOG_LINES
+        {
+            job.log.push(line);
+        }
+    }
+
+    pub fn start(&mut self, id: &str) {
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.status = JobStatus::Running;
+            job.started_at = Some(Utc::now());
+            job.delay_reason = None;
+            job.delayed_until = None;
+        }
+    }
+
+    /// Records why a queued job cannot start yet. Returns whether the reason
+    /// changed, so callers only log new ones.
+    pub fn delay(&mut self, id: &str, reason: String, until: Option<DateTime<Utc>>) -> bool {
+        let Some(job) = self.jobs.get_mut(id) else {
+            return false;
+        };
+        job.delayed_until = until;
+        if job.delay_reason.as_ref() == Some(&reason) {
+            return false;
+        }
+        job.delay_reason = Some(reason);
+        true
+    }
+
+    pub fn finish(&mut self, id: &str, status: JobStatus, error: Option<String>) {
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.status = status;
+            job.finished_at = Some(Utc::now());
+            job.error = error;
+            job.done.notify_waiters();
+        }
+    }
+
+    /// Mean run time of recently finished jobs, used for queue ETAs.
+    pub fn average_duration(&self) -> Option<chrono::Duration> {
+        let durations: Vec<chrono::Duration> = self
+            .jobs
+            .values()
+            .filter_map(|job| Some(job.finished_at? - job.started_at?))
+            .collect();
+        let count = i32::try_from(durations.len()).ok().filter(|n| *n > 0)?;
+        Some(durations.into_iter().sum::<chrono::Duration>() / count)
+    }
+
+    /// The signal a running job listens on to abort its lego process.
+    pub fn cancel_signal(&self, id: &str) -> Option<Arc<Notify>> {
+        self.jobs.get(id).map(|job| job.cancel.clone())
+    }
+
+    /// Asks a running job to stop. The job records itself as cancelled once
+    /// its process is gone. Queued jobs are cancelled through the queue.
+    /// Returns `None` for an unknown job and the status of a job that is not
+    /// running.
+    pub fn cancel(&self, id: &str) -> Option<Result<(), JobStatus>> {
+        let job = self.jobs.get(id)?;
+        if job.status != JobStatus::Running {
+            return Some(Err(job.status));
+        }
+        // `notify_one` keeps a permit, so a job that has not started waiting
+        // yet still sees the request.
+        job.cancel.notify_one();
+        Some(Ok(()))
+    }
+
+    pub fn cancel_all(&self) -> usize {
+        let running: Vec<&JobRecord> = self
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatus::Running)
+            .collect();
+        for job in &running {
+            job.cancel.notify_one();
+        }
+        running.len()
+    }
+
+    pub fn running_count(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|job| job.status == JobStatus::Running)
+            .count()
+    }
+}
+
+/// Writes lego output into one job's log, scrubbing known secret values.
+#[derive(Clone)]
+pub struct JobLog {
+    jobs: Arc<RwLock<JobStore>>,
+    id: String,
+    secrets: Vec<String>,
+}
+
+impl JobLog {
+    pub fn new(jobs: Arc<RwLock<JobStore>>, id: &str, secrets: Vec<String>) -> Self {
+        Self {
+            jobs,
+            id: id.to_string(),
+            secrets: secrets.into_iter().filter(|s| !s.is_empty()).collect(),
+        }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        redact::redact(text, &self.secrets)
+    }
+
+    pub fn append(&self, line: &str) {
+        let line = self.redact(line);
+        self.jobs.write().append_log(&self.id, line);
+    }
+}
+
+/// Waits until job `id` has finished and returns its final status, or `None`
+/// if the job is unknown.
+pub async fn wait_finished(jobs: &Arc<RwLock<JobStore>>, id: &str) -> Option<JobStatus> {
+    loop {
+        let done = jobs.read().get(id)?.done.clone();
+        let notified = done.notified();
+        tokio::pin!(notified);
+        // Register before checking, so a finish in between is not missed.
+        notified.as_mut().enable();
+        let status = jobs.read().get(id)?.status;
+        if status.is_finished() {
+            return Some(status);
+        }
+        notified.await;
+    }
+}
@@ -0,0 +1,572 @@
+/* src/lego.rs */
+
+use crate::{
+    acme,
+    acme::CommandType,
+    config::{self, AppConfig, CertificateEntry, DnsProviderConfig, ProviderType},
+    hook, redact, renewal, secrets,
+    template::{self, TemplateError},
+    zone,
+};
+use fancy_regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+
+pub const LEGO_PROGRAM: &str = "lego";
+pub const DEFAULT_CA: &str = "https://acme-v02.api.letsencrypt.org/directory";
+const DEFAULT_KEY_TYPE: &str = "ec256";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A fully resolved process to run: no shell is involved unless the provider
+/// explicitly opted into the legacy `shell` type.
+#[derive(Debug, Clone)]
+pub struct LegoInvocation {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub timeout: Duration,
+    /// Rendered secret values, scrubbed from anything logged about this run.
+    pub secrets: Vec<String>,
+}
+
+impl LegoInvocation {
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args).envs(&self.env);
+        cmd
+    }
+
+    /// A printable form of the invocation. Environment values are never
+    /// shown, and secrets are masked wherever else they appear.
+    pub fn display(&self) -> String {
+        let mut parts: Vec<String> = self.env.keys().map(|k| format!("{}=***", k)).collect();
+        if self.program == "sh" {
+            parts.push("sh -c".to_string());
+            parts.extend(
+                self.args
+                    .iter()
+                    .skip(1)
+                    .map(|a| sanitize_command_for_log(a)),
+            );
+        } else {
+            parts.push(self.program.clone());
+            parts.extend(self.args.iter().cloned());
+        }
+        redact::redact(&parts.join(" "), &self.secrets)
+    }
+}
+
+fn sanitize_command_for_log(command: &str) -> String {
+    let re = Regex::new(r#"(?i)([^=\s]+)=(['"]?)[^'"\s]+\2(?=\s+lego)"#).unwrap();
+    re.replace_all(command, "$1=***").to_string()
+}
+
+/// Builds the lego invocation for `entry` from its provider config(s).
+pub async fn build_invocation(
+    entry: &CertificateEntry,
+    config: &AppConfig,
+    command_type: CommandType,
+) -> Result<LegoInvocation, Box<dyn std::error::Error + Send + Sync>> {
+    if entry.sans.is_empty() {
+        return Err(format!("Certificate '{}' has no SANs", entry.id).into());
+    }
+    let provider_config_path = config
+        .dir_path
+        .join(format!("{}.dns.toml", entry.dns_provider.trim()));
+
+    if !provider_config_path.exists() {
+        return Err(format!(
+            "DNS provider config not found at {:?}",
+            provider_config_path
+        )
+        .into());
+    }
+
+    let mut provider_config = config::load_dns_provider_config(&provider_config_path).await?;
+    let provider = entry.dns_provider.trim();
+    secrets::resolve(provider, &mut provider_config).await?;
+    let timeout = provider_config
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let renew_days = renewal::renew_days(
+        acme::scheduled_renewal(entry, config).await.ok(),
+        entry.renew_before(config),
+    );
+
+    let mut invocation = build_provider_invocation(
+        entry,
+        config,
+        &provider_config,
+        &provider_config_path,
+        command_type,
+        renew_days,
+    )
+    .await?;
+    invocation.timeout = timeout;
+    // Secret-source values reach lego and its hooks only through the environment.
+    invocation
+        .env
+        .extend(secrets::env_for(provider, &provider_config));
+    invocation.secrets.extend(provider_config.secret_values());
+    invocation.secrets.extend(
+        invocation
+            .env
+            .iter()
+            .filter(|(key, value)| provider_config.is_secret(key) && !value.is_empty())
+            .map(|(_, value)| value.clone()),
+    );
+    Ok(invocation)
+}
+
+async fn build_provider_invocation(
+    entry: &CertificateEntry,
+    config: &AppConfig,
+    provider_config: &DnsProviderConfig,
+    provider_config_path: &Path,
+    command_type: CommandType,
+    renew_days: i64,
+) -> Result<LegoInvocation, Box<dyn std::error::Error + Send + Sync>> {
+    if !entry.san_providers.is_empty() {
+        // One lego run can only use one DNS mechanism, so SANs with their own
+        // providers are all routed through our hook. The default provider
+        // has to be a hook provider too, even if every SAN is routed elsewhere.
+        check_hook_provider(&entry.dns_provider, provider_config)?;
+        let mut invocation = build_hook_invocation(
+            entry,
+            provider_config,
+            provider_config_path,
+            command_type,
+            renew_days,
+        )
+        .await?;
+        add_hook_routes(&mut invocation, entry, config).await?;
+        return Ok(invocation);
+    }
+
+    match provider_config.provider_type {
+        ProviderType::Lego => {
+            build_lego_invocation(entry, provider_config, command_type, renew_days).await
+        }
+        ProviderType::Exec | ProviderType::Webhook => {
+            check_hook_provider(&entry.dns_provider, provider_config)?;
+            build_hook_invocation(
+                entry,
+                provider_config,
+                provider_config_path,
+                command_type,
+                renew_days,
+            )
+            .await
+        }
+        ProviderType::Shell => {
+            build_shell_invocation(entry, provider_config, command_type, renew_days).await
+        }
+    }
+}
+
+/// Placeholder values shared by every kind of template.
+struct TemplateContext<'a> {
+    entry: &'a CertificateEntry,
+    provider_config: &'a DnsProviderConfig,
+    san_flags: String,
+    zone: Option<String>,
+    renew_days: i64,
+}
+
+impl<'a> TemplateContext<'a> {
+    async fn new(
+        entry: &'a CertificateEntry,
+        provider_config: &'a DnsProviderConfig,
+        renew_days: i64,
+        templates: &[&str],
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let san_flags = entry
+            .sans
+            .iter()
+            .map(|san| Ok(format!("-d {}", shlex::try_quote(san)?)))
+            .collect::<Result<Vec<_>, shlex::QuoteError>>()?
+            .join(" ");
+
+        // Zone detection costs DNS round trips, so only do it when a template asks.
+        let wants_zone = templates.iter().any(|t| {
+            template::placeholders(t)
+                .iter()
+                .any(|name| name == "ZONE" || name == "RECORD_NAME")
+        });
+        let zone = if wants_zone {
+            Some(match provider_config.var_str("zone") {
+                Some(zone) => zone,
+                None => zone::find_zone(entry.primary_domain()).await?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            entry,
+            provider_config,
+            san_flags,
+            zone,
+            renew_days,
+        })
+    }
+
+    fn render(&self, template: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        template::render(template, |key| self.lookup(key))
+            .map_err(|e| format!("{} in '{}'", e, template).into())
+    }
+
+    /// Renders a command-line argument. Secret-source variables are refused,
+    /// since arguments are visible to every user on the host.
+    fn render_arg(
+        &self,
+        template: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(key) = template::placeholders(template)
+            .into_iter()
+            .find(|key| self.provider_config.is_secret_source(key))
+        {
+            return Err(format!(
+                "'{}' comes from a secret source and can only be used in [env]",
+                key
+            )
+            .into());
+        }
+        self.render(template)
+    }
+
+    /// Renders a shell command. Secret-source variables become references to
+    /// the environment variable that carries them.
+    fn render_shell(
+        &self,
+        template: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        template::render(template, |key| {
+            if self.provider_config.is_secret_source(key) {
+                let var = secrets::env_name(self.entry.dns_provider.trim(), key);
+                Some(format!("\"${{{}}}\"", var))
+            } else {
+                self.lookup(key)
+            }
+        })
+        .map_err(|e| format!("{} in 'cmd'", e).into())
+    }
+
+    fn lookup(&self, key: &str) -> Option<String> {
+        let domain = self.entry.primary_domain();
+        match key.to_ascii_uppercase().as_str() {
+            "DOMAIN" => Some(domain.to_string()),
+            "ID" => Some(self.entry.id.clone()),
+            "SANS" => Some(self.san_flags.clone()),
+            "ZONE" => self.zone.clone(),
+            "RECORD_NAME" => self
+                .zone
+                .as_deref()
+                .map(|zone| zone::challenge_record_name(domain, zone)),
+            "KEY_TYPE" => Some(key_type(self.provider_config)),
+            "RENEW_DAYS" => Some(self.renew_days.to_string()),
+            _ => self.provider_config.var_str(key),
+        }
+    }
+}
+
+/// The ACME directory a provider config points lego at.
+pub fn ca_url(provider_config: &DnsProviderConfig) -> String {
+    provider_config
+        .var_str("ca")
+        .unwrap_or_else(|| DEFAULT_CA.to_string())
+}
+
+/// `lego --email .. --server .. --dns <dns> -d .. [args] run|renew [..]`
+fn lego_args(
+    entry: &CertificateEntry,
+    provider_config: &DnsProviderConfig,
+    dns: &str,
+    command_type: CommandType,
+    ctx: &TemplateContext<'_>,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let email = provider_config
+        .var_str("email")
+        .ok_or("Provider config is missing 'email'")?;
+    let ca = ca_url(provider_config);
+
+    let mut args = vec![
+        "--accept-tos".to_string(),
+        "--email".to_string(),
+        email,
+        "--server".to_string(),
+        ca,
+        "--dns".to_string(),
+        dns.to_string(),
+    ];
+    if provider_config.var_str("key_type").is_some() {
+        args.push("--key-type".to_string());
+        args.push(key_type(provider_config));
+    }
+    for san in &entry.sans {
+        args.push("-d".to_string());
+        args.push(san.clone());
+    }
+    for arg in &provider_config.args {
+        args.push(ctx.render_arg(arg)?);
+    }
+    match command_type {
+        CommandType::Run => {
+            args.push("run".to_string());
+            for arg in &provider_config.run_args {
+                args.push(ctx.render_arg(arg)?);
+            }
+        }
+        CommandType::Renew => {
+            args.push("renew".to_string());
+            match &provider_config.renew_args {
+                Some(renew_args) => {
+                    for arg in renew_args {
+                        args.push(ctx.render_arg(arg)?);
+                    }
+                }
+                None => args.extend(["--days".to_string(), ctx.renew_days.to_string()]),
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn key_type(provider_config: &DnsProviderConfig) -> String {
+    provider_config
+        .var_str("key_type")
+        .unwrap_or_else(|| DEFAULT_KEY_TYPE.to_string())
+}
+
+/// Required placeholders in the provider's lego-facing templates that have
+/// no value, e.g. a variable that is set to an empty string.
+pub fn unresolved_placeholders(provider_config: &DnsProviderConfig) -> Vec<String> {
+    let mut templates = provider_templates(provider_config);
+    templates.extend(provider_config.cmd.as_deref());
+    templates.extend(provider_config.renew.as_deref());
+
+    let mut unresolved = Vec::new();
+    for template in templates {
+        let lookup = |key: &str| {
+            if template::CERT_BUILTINS.contains(&key) {
+                Some(key.to_string())
+            } else {
+                provider_config.var_str(key)
+            }
+        };
+        if let Err(TemplateError::Missing(names)) = template::render(template, lookup) {
+            unresolved.extend(names);
+        }
+    }
+    unresolved.sort();
+    unresolved.dedup();
+    unresolved
+}
+
+fn provider_templates(provider_config: &DnsProviderConfig) -> Vec<&str> {
+    provider_config
+        .env
+        .values()
+        .chain(provider_config.args.iter())
+        .chain(provider_config.run_args.iter())
+        .chain(provider_config.renew_args.iter().flatten())
+        .map(String::as_str)
+        .collect()
+}
+
+fn render_env(
+    provider_config: &DnsProviderConfig,
+    ctx: &TemplateContext<'_>,
+) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error + Send + Sync>> {
+    provider_config
+        .env
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), ctx.render(v)?)))
+        .collect()
+}
+
+async fn build_lego_invocation(
+    entry: &CertificateEntry,
+    provider_config: &DnsProviderConfig,
+    command_type: CommandType,
+    renew_days: i64,
+) -> Result<LegoInvocation, Box<dyn std::error::Error + Send + Sync>> {
+    if provider_config.cmd.is_some() {
+        return Err(
+            "Provider config has a shell 'cmd' template; set type = \"shell\" to keep using it"
+                .into(),
+        );
+    }
+    let dns = provider_config
+        .dns
+        .as_deref()
+        .ok_or("Provider config is missing 'dns' (the lego DNS provider code)")?;
+
+    let ctx = TemplateContext::new(
+        entry,
+        provider_config,
+        renew_days,
+        &provider_templates(provider_config),
+    )
+    .await?;
+    let args = lego_args(entry, provider_config, dns, command_type, &ctx)?;
+    let env = render_env(provider_config, &ctx)?;
+
+    Ok(LegoInvocation {
+        program: LEGO_PROGRAM.to_string(),
+        args,
+        env,
+        timeout: DEFAULT_TIMEOUT,
+        secrets: Vec::new(),
+    })
+}
+
+async fn build_hook_invocation(
+    entry: &CertificateEntry,
+    provider_config: &DnsProviderConfig,
+    provider_config_path: &Path,
+    command_type: CommandType,
+    renew_days: i64,
+) -> Result<LegoInvocation, Box<dyn std::error::Error + Send + Sync>> {
+    let ctx = TemplateContext::new(
+        entry,
+        provider_config,
+        renew_days,
+        &provider_templates(provider_config),
+    )
+    .await?;
+    let args = lego_args(entry, provider_config, "exec", command_type, &ctx)?;
+
+    let mut env = render_env(provider_config, &ctx)?;
+    env.insert(
+        "EXEC_PATH".to_string(),
+        std::env::current_exe()?.to_string_lossy().into_owned(),
+    );
+    env.insert(
+        hook::HOOK_CONFIG_ENV.to_string(),
+        provider_config_path.to_string_lossy().into_owned(),
+    );
+    if let Some(timeout) = provider_config.var_str("propagation_timeout") {
+        env.insert("EXEC_PROPAGATION_TIMEOUT".to_string(), timeout);
+    }
+
+    Ok(LegoInvocation {
+        program: LEGO_PROGRAM.to_string(),
+        args,
+        env,
+        timeout: DEFAULT_TIMEOUT,
+        secrets: Vec::new(),
+    })
+}
+
+async fn build_shell_invocation(
+    entry: &CertificateEntry,
+    provider_config: &DnsProviderConfig,
+    command_type: CommandType,
+    renew_days: i64,
+) -> Result<LegoInvocation, Box<dyn std::error::Error + Send + Sync>> {
+    let command_template = match command_type {
+        CommandType::Run => provider_config.cmd.clone(),
+        CommandType::Renew => provider_config
+            .renew
+            .clone()
+            .or_else(|| provider_config.cmd.clone()),
+    }
+    .ok_or("Shell provider config is missing 'cmd'")?;
+
+    let ctx =
+        TemplateContext::new(entry, provider_config, renew_days, &[&command_template]).await?;
+
+    Ok(LegoInvocation {
+        program: "sh".to_string(),
+        args: vec!["-c".to_string(), ctx.render_shell(&command_template)?],
+        env: BTreeMap::new(),
+        timeout: DEFAULT_TIMEOUT,
+        secrets: Vec::new(),
+    })
+}
+
+fn check_hook_provider(
+    name: &str,
+    provider_config: &DnsProviderConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if !matches!(
+        provider_config.provider_type,
+        ProviderType::Exec | ProviderType::Webhook
+    ) {
+        return Err(format!(
+            "DNS provider '{}' is of type {}; routing SANs to different providers \
+             needs every provider involved to be of type exec or webhook, since lego \
+             runs a single native provider per certificate",
+            name,
+            format!("{:?}", provider_config.provider_type).to_lowercase()
+        )
+        .into());
+    }
+    if provider_config.present.is_none() || provider_config.cleanup.is_none() {
+        return Err(format!("DNS provider '{}' needs both 'present' and 'cleanup'", name).into());
+    }
+    Ok(())
+}
+
+/// Checks up front that a certificate routing SANs to different providers
+/// only uses exec or webhook providers. Configs that fail to load are left
+/// for the job itself to report.
+pub async fn check_routing(entry: &CertificateEntry, config: &AppConfig) -> Result<(), String> {
+    if entry.san_providers.is_empty() {
+        return Ok(());
+    }
+    for provider in entry.providers() {
+        let path = config.dir_path.join(format!("{}.dns.toml", provider));
+        if let Ok(provider_config) = config::load_dns_provider_config(&path).await {
+            check_hook_provider(provider, &provider_config).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Routes each SAN's challenge name to the provider config that answers it,
+/// passing every routed provider's secrets along to the hook.
+async fn add_hook_routes(
+    invocation: &mut LegoInvocation,
+    entry: &CertificateEntry,
+    config: &AppConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut routes: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for san in &entry.sans {
+        let provider = entry.provider_for(san);
+        let path = config.dir_path.join(format!("{}.dns.toml", provider));
+        let mut provider_config = config::load_dns_provider_config(&path)
+            .await
+            .map_err(|e| format!("Failed to load DNS provider '{}': {}", provider, e))?;
+        check_hook_provider(provider, &provider_config)?;
+        secrets::resolve(provider, &mut provider_config).await?;
+        invocation
+            .env
+            .extend(secrets::env_for(provider, &provider_config));
+        invocation.secrets.extend(provider_config.secret_values());
+
+        // `*.example.com` and `example.com` share one challenge record.
+        let name = san.trim_start_matches("*.").to_ascii_lowercase();
+        match routes.get(&name) {
+            Some(existing) if *existing != path => {
+                return Err(format!(
+                    "SANs for '{}' in certificate '{}' use different DNS providers",
+                    name, entry.id
+                )
+                .into());
+            }
+            _ => {
+                routes.insert(name, path);
+            }
+        }
+    }
+    invocation.env.insert(
+        hook::HOOK_ROUTES_ENV.to_string(),
+        serde_json::to_string(&routes)?,
+    );
+    Ok(())
+}
@@ -2,21 +2,81 @@
 
 use fancy_log::{LogLevel, log, set_log_level};
 use lazy_motd::lazy_motd;
+use std::time::Duration;
 
 mod acme;
+mod budget;
 mod config;
+mod doctor;
 mod handlers;
+mod history;
+mod hook;
 mod init;
+mod jobs;
+mod lego;
+mod metadata;
+mod pause;
+mod persist;
+mod queue;
+mod redact;
+mod renewal;
 mod response;
+mod retry;
+mod schedule;
+mod secrets;
 mod server;
 mod state;
+mod status;
 mod tasks;
+mod template;
+mod validate;
+mod zone;
+
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // --- DNS hook mode (invoked by lego's `exec` provider) ---
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(invocation) = hook::parse_invocation(&args) {
+        let result = match invocation {
+            Ok(invocation) => hook::run(invocation).await,
+            Err(usage) => Err(usage.into()),
+        };
+        if let Err(e) = result {
+            log(LogLevel::Error, &format!("DNS hook failed: {}", e));
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // --- Initialization ---
     let app_config = config::AppConfig::load();
     set_log_level(app_config.log_level);
+
+    // --- Preflight-only mode ---
+    if args.get(1).map(String::as_str) == Some("doctor") {
+        let report = doctor::run(&app_config).await;
+        report.print();
+        if report.has_failures() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // --- Dry run: print what would be executed for configured certificates ---
+    if args.iter().any(|a| a == "--dry-run") {
+        let ids: Vec<&String> = args
+            .iter()
+            .skip(1)
+            .filter(|a| !a.starts_with("--"))
+            .collect();
+        if !tasks::print_dry_run(&app_config, &ids).await {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     lazy_motd!();
 
     // --- First-time setup check ---
@@ -32,17 +92,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    // --- Startup self-check ---
+    let report = doctor::run(&app_config).await;
+    report.print();
+    if report.has_failures() {
+        log(
+            LogLevel::Warn,
+            "Startup self-check found problems; affected certificates will fail until they are fixed. Run `lazy-acme doctor` for details.",
+        );
+    }
+
     // --- Create Shared State and Start Services ---
     log(LogLevel::Info, "Configuration loaded. Starting services...");
     let app_state = state::AppState::new(app_config);
 
+    // Jobs left over from the last run go first, then the worker picks up
+    // everything queued from here on.
+    let rate_events = budget::load(&app_state.config.dir_path).await;
+    app_state.budget.write().restore(rate_events);
+    let holds = pause::load(&app_state.config.dir_path).await;
+    app_state.holds.write().restore(holds);
+    let statuses = status::load(&app_state.config.dir_path).await;
+    for id in app_state.domains.write().restore(statuses) {
+        log(
+            LogLevel::Warn,
+            &format!(
+                "The last run for '{}' was interrupted; it will run again.",
+                id
+            ),
+        );
+    }
+    tasks::restore_queue(&app_state).await;
+    let entries = tasks::load_startup_config(&app_state).await;
+    tasks::spawn_queue_worker(app_state.clone());
+
     // Spawn the background task for initial certificate checks.
     // This runs concurrently with the web server.
-    tasks::spawn_startup_check_task(app_state.clone());
+    tasks::spawn_startup_check_task(app_state.clone(), entries);
 
     // Start the web server. This is a blocking call that will run until a shutdown signal is received.
-    server::run_server(app_state).await?;
+    server::run_server(app_state.clone()).await?;
+
+    // Keep queued and running jobs on disk for the next start, then stop
+    // running lego processes instead of leaving them orphaned.
+    app_state.queue.write().close();
+    app_state.domains.write().close();
+    let cancelled = app_state.jobs.read().cancel_all();
+    if cancelled > 0 {
+        log(
+            LogLevel::Info,
+            &format!("Cancelling {} running job(s) before exit...", cancelled),
+        );
+        let deadline = tokio::time::Instant::now() + SHUTDOWN_GRACE_PERIOD;
+        while app_state.jobs.read().running_count() > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
 
+    persist::flush().await;
     log(LogLevel::Info, "Application has shut down gracefully.");
     Ok(())
 }
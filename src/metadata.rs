@@ -0,0 +1,158 @@
+/* src/metadata.rs */
+
+use crate::config::CertificateEntry;
+use fancy_log::{LogLevel, log};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// The `<name>.json` resource file lego writes next to each certificate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CertificateMetadata {
+    pub domain: String,
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default, alias = "certUrl")]
+    pub cert_url: String,
+    #[serde(default, alias = "certStableUrl")]
+    pub cert_stable_url: String,
+    /// File name without extension, shared by the `.crt`, `.key` and `.json` files.
+    #[serde(skip_deserializing)]
+    pub stem: String,
+}
+
+impl CertificateMetadata {
+    /// Every name the certificate covers. Older lego versions only record `domain`.
+    pub fn names(&self) -> BTreeSet<String> {
+        let names = if self.domains.is_empty() {
+            std::slice::from_ref(&self.domain)
+        } else {
+            self.domains.as_slice()
+        };
+        names.iter().map(|n| n.to_ascii_lowercase()).collect()
+    }
+
+    /// Whether the certificate covers a wildcard name. Without a `domains`
+    /// list only the main domain is recorded, so the SANs of the `entry` it
+    /// was issued for stand in.
+    pub fn has_wildcard(&self, entry: &CertificateEntry) -> bool {
+        if self.domains.is_empty() {
+            entry.sans.iter().any(|n| n.starts_with("*."))
+        } else {
+            self.names().iter().any(|n| n.starts_with("*."))
+        }
+    }
+
+    pub fn path(&self, cert_dir: &Path, extension: &str) -> PathBuf {
+        cert_dir.join(format!("{}.{}", self.stem, extension))
+    }
+
+    /// A resource belongs to `entry` when it covers exactly its SANs; for
+    /// resources without a `domains` list, when its main domain is the first
+    /// SAN, which lego names the files after.
+    fn matches(&self, entry: &CertificateEntry) -> bool {
+        if self.domains.is_empty() {
+            return entry
+                .sans
+                .first()
+                .is_some_and(|first| first.eq_ignore_ascii_case(&self.domain));
+        }
+        let sans: BTreeSet<String> = entry.sans.iter().map(|s| s.to_ascii_lowercase()).collect();
+        self.names() == sans
+    }
+}
+
+/// Reads every resource file in `cert_dir`. Unreadable files are logged and skipped.
+pub async fn load_all(cert_dir: &Path) -> Vec<CertificateMetadata> {
+    let mut resources = Vec::new();
+    let Ok(mut dir) = fs::read_dir(cert_dir).await else {
+        return resources;
+    };
+    while let Ok(Some(file)) = dir.next_entry().await {
+        let path = file.path();
+        let Some(stem) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        let parsed = match fs::read(&path).await {
+            Ok(content) => {
+                serde_json::from_slice::<CertificateMetadata>(&content).map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        match parsed {
+            Ok(mut resource) => {
+                resource.stem = stem.to_string();
+                resources.push(resource);
+            }
+            Err(e) => log(
+                LogLevel::Warn,
+                &format!("Skipping unreadable lego resource {:?}: {}", path, e),
+            ),
+        }
+    }
+    resources
+}
+
+/// Finds the resource lego wrote for `entry` whose certificate and key are both present.
+pub async fn find(entry: &CertificateEntry, cert_dir: &Path) -> Option<CertificateMetadata> {
+    for resource in load_all(cert_dir).await {
+        if !resource.matches(entry) {
+            continue;
+        }
+        let crt = fs::metadata(resource.path(cert_dir, "crt")).await.is_ok();
+        let key = fs::metadata(resource.path(cert_dir, "key")).await.is_ok();
+        if crt && key {
+            return Some(resource);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SanEntry;
+
+    fn entry(id: &str, sans: &[&str]) -> CertificateEntry {
+        let sans = sans.iter().map(|s| SanEntry::Name(s.to_string())).collect();
+        CertificateEntry::new(id.to_string(), sans, "dns".to_string())
+    }
+
+    fn resource(domain: &str, domains: &[&str]) -> CertificateMetadata {
+        CertificateMetadata {
+            domain: domain.to_string(),
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            cert_url: String::new(),
+            cert_stable_url: String::new(),
+            stem: domain.replace('*', "_"),
+        }
+    }
+
+    #[test]
+    fn main_domain_only_matches_the_first_san() {
+        let a = entry("a", &["a.com", "b.com"]);
+        let b = entry("b", &["B.com", "c.com"]);
+        let issued_b = resource("b.com", &[]);
+        assert!(!issued_b.matches(&a));
+        assert!(issued_b.matches(&b));
+    }
+
+    #[test]
+    fn domains_list_must_match_exactly() {
+        let a = entry("a", &["a.com", "b.com"]);
+        assert!(resource("a.com", &["b.com", "a.com"]).matches(&a));
+        assert!(!resource("a.com", &["a.com"]).matches(&a));
+    }
+
+    #[test]
+    fn wildcard_falls_back_to_the_entry_sans() {
+        let a = entry("a", &["example.com", "*.example.com"]);
+        assert!(resource("example.com", &[]).has_wildcard(&a));
+        assert!(!resource("example.com", &["example.com"]).has_wildcard(&a));
+    }
+}
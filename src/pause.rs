@@ -0,0 +1,144 @@
+/* src/pause.rs */
+
+use crate::persist::StateFile;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const PAUSE_FILE: &str = "pause.json";
+
+/// Why the scheduler is paused or a certificate is disabled, and until when.
+/// Without `until` it lasts until it is lifted.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Hold {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Hold {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_none_or(|until| now < until)
+    }
+
+    /// `what`, followed by the reason if there is one.
+    pub fn describe(&self, what: &str) -> String {
+        match &self.reason {
+            Some(reason) => format!("{}: {}", what, reason),
+            None => what.to_string(),
+        }
+    }
+}
+
+/// Reads `disabled = true` or `disabled = { reason = "..", until = ".." }`.
+pub fn deserialize_flag<'de, D>(deserializer: D) -> Result<Option<Hold>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        On(bool),
+        Hold(Hold),
+    }
+    Ok(match Option::<Flag>::deserialize(deserializer)? {
+        Some(Flag::On(true)) => Some(Hold::default()),
+        Some(Flag::Hold(hold)) => Some(hold),
+        Some(Flag::On(false)) | None => None,
+    })
+}
+
+/// Holds set through the API, mirrored to `pause.json`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct SavedHolds {
+    #[serde(default)]
+    pub paused: Option<Hold>,
+    #[serde(default)]
+    pub disabled: BTreeMap<String, Hold>,
+}
+
+/// Whether the scheduler is paused and which certificates are disabled,
+/// from the API and from `config.toml`. Expired holds are ignored.
+pub struct Holds {
+    saved: SavedHolds,
+    /// The top-level `pause` in `config.toml`, as of the last time it was read.
+    config_pause: Option<Hold>,
+    file: StateFile,
+}
+
+impl Holds {
+    pub fn new(dir_path: &Path) -> Self {
+        Self {
+            saved: SavedHolds::default(),
+            config_pause: None,
+            file: state_file(dir_path),
+        }
+    }
+
+    pub fn restore(&mut self, saved: SavedHolds) {
+        self.saved = saved;
+    }
+
+    pub fn set_config_pause(&mut self, hold: Option<Hold>) {
+        self.config_pause = hold;
+    }
+
+    /// The pause in effect, preferring the one set through the API.
+    pub fn paused(&self, now: DateTime<Utc>) -> Option<&Hold> {
+        self.saved
+            .paused
+            .as_ref()
+            .into_iter()
+            .chain(self.config_pause.as_ref())
+            .find(|hold| hold.is_active(now))
+    }
+
+    pub fn config_paused(&self, now: DateTime<Utc>) -> bool {
+        self.config_pause.as_ref().is_some_and(|h| h.is_active(now))
+    }
+
+    pub fn pause(&mut self, hold: Hold) {
+        self.saved.paused = Some(hold);
+        self.save();
+    }
+
+    /// Lifts the API pause. Returns whether there was one.
+    pub fn resume(&mut self) -> bool {
+        let resumed = self.saved.paused.take().is_some();
+        self.save();
+        resumed
+    }
+
+    /// The API hold on certificate `id`, if it is in effect.
+    pub fn disabled(&self, id: &str, now: DateTime<Utc>) -> Option<&Hold> {
+        self.saved.disabled.get(id).filter(|h| h.is_active(now))
+    }
+
+    pub fn disable(&mut self, id: &str, hold: Hold) {
+        self.saved.disabled.insert(id.to_string(), hold);
+        self.save();
+    }
+
+    /// Lifts the API hold on `id`. Returns whether there was one.
+    pub fn enable(&mut self, id: &str) -> bool {
+        let enabled = self.saved.disabled.remove(id).is_some();
+        self.save();
+        enabled
+    }
+
+    fn save(&self) {
+        self.file.save(&self.saved);
+    }
+}
+
+fn state_file(dir_path: &Path) -> StateFile {
+    StateFile::new(dir_path.join(PAUSE_FILE), "pause state")
+}
+
+/// Reads the holds a previous run left in `pause.json`.
+pub async fn load(dir_path: &Path) -> SavedHolds {
+    state_file(dir_path).load().await
+}
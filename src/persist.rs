@@ -0,0 +1,122 @@
+/* src/persist.rs */
+
+use fancy_log::{LogLevel, log};
+use parking_lot::Mutex;
+use serde::{Serialize, de::DeserializeOwned};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Files with a write queued or in progress, across all `StateFile`s.
+static WRITING: AtomicUsize = AtomicUsize::new(0);
+
+/// A JSON file mirroring some in-memory state, such as `queue.json`.
+///
+/// `save` serializes right away, so it can be called under the lock that
+/// guards the state, and writes the file on a blocking thread. Writes to one
+/// file happen in order; a write queued behind another replaces any older
+/// one still waiting.
+pub struct StateFile {
+    path: PathBuf,
+    /// What the file holds, for log messages.
+    what: &'static str,
+    pending: Arc<Mutex<Pending>>,
+}
+
+#[derive(Default)]
+struct Pending {
+    content: Option<Vec<u8>>,
+    writing: bool,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf, what: &'static str) -> Self {
+        Self {
+            path,
+            what,
+            pending: Arc::default(),
+        }
+    }
+
+    pub fn save<T: Serialize + ?Sized>(&self, value: &T) {
+        let content = match serde_json::to_vec_pretty(value) {
+            Ok(content) => content,
+            Err(e) => {
+                log(
+                    LogLevel::Error,
+                    &format!("Failed to save {} to {:?}: {}", self.what, self.path, e),
+                );
+                return;
+            }
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            write(&self.path, self.what, &content);
+            return;
+        };
+        {
+            let mut pending = self.pending.lock();
+            pending.content = Some(content);
+            if pending.writing {
+                return;
+            }
+            pending.writing = true;
+        }
+        WRITING.fetch_add(1, Ordering::SeqCst);
+        let (path, what, pending) = (self.path.clone(), self.what, self.pending.clone());
+        runtime.spawn_blocking(move || {
+            loop {
+                let content = {
+                    let mut pending = pending.lock();
+                    let content = pending.content.take();
+                    pending.writing = content.is_some();
+                    content
+                };
+                let Some(content) = content else { break };
+                write(&path, what, &content);
+            }
+            WRITING.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Reads what a previous run saved. A missing file gives the default
+    /// value; an unreadable one is logged and ignored.
+    pub async fn load<T: DeserializeOwned + Default>(&self) -> T {
+        let Ok(content) = tokio::fs::read(&self.path).await else {
+            return T::default();
+        };
+        match serde_json::from_slice(&content) {
+            Ok(value) => value,
+            Err(e) => {
+                log(
+                    LogLevel::Error,
+                    &format!(
+                        "Ignoring unreadable {} in {:?}: {}",
+                        self.what, self.path, e
+                    ),
+                );
+                T::default()
+            }
+        }
+    }
+}
+
+/// Replaces `path` with `content` through a temporary file, so a crash never
+/// leaves it half written.
+fn write(path: &Path, what: &str, content: &[u8]) {
+    let tmp = path.with_extension("json.tmp");
+    let result = std::fs::write(&tmp, content).and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        log(
+            LogLevel::Error,
+            &format!("Failed to save {} to {:?}: {}", what, path, e),
+        );
+    }
+}
+
+/// Waits until every queued write has reached disk.
+pub async fn flush() {
+    while WRITING.load(Ordering::SeqCst) > 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
@@ -0,0 +1,343 @@
+/* src/queue.rs */
+
+use crate::config::{CertificateEntry, SanEntry};
+use crate::jobs::{JobKind, JobStore, Trigger};
+use crate::persist::StateFile;
+use crate::renewal::RenewBefore;
+use crate::retry::RetryOverrides;
+use crate::schedule::MaintenanceWindow;
+use chrono::{DateTime, Utc};
+use fancy_log::{LogLevel, log};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const QUEUE_FILE: &str = "queue.json";
+/// Assumed run time of a job until some have finished.
+const DEFAULT_JOB_SECS: i64 = 120;
+
+/// A job waiting for (or holding) a lego run.
+#[derive(Clone)]
+pub struct QueuedJob {
+    pub job_id: String,
+    pub entry: CertificateEntry,
+    pub kind: JobKind,
+    pub trigger: Trigger,
+    /// Whether a successful run adds the certificate to config.toml.
+    pub persist: bool,
+    /// When the current certificate expires; `None` when there is none yet.
+    pub deadline: Option<DateTime<Utc>>,
+    pub enqueued_at: DateTime<Utc>,
+    /// Which run for this certificate this is; retries count up from 1.
+    pub attempt: u32,
+    /// The job waits until this time before it may start, and why.
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_before_reason: Option<String>,
+}
+
+impl QueuedJob {
+    /// Missing certificates first, then the ones closest to expiry.
+    fn priority(&self) -> (bool, Option<DateTime<Utc>>, DateTime<Utc>) {
+        (self.deadline.is_some(), self.deadline, self.enqueued_at)
+    }
+}
+
+/// The on-disk form of a queued job.
+#[derive(Serialize, Deserialize)]
+pub struct PersistedJob {
+    pub id: String,
+    pub sans: Vec<SanEntry>,
+    pub dns_provider: String,
+    pub kind: JobKind,
+    #[serde(default)]
+    pub trigger: Trigger,
+    pub persist: bool,
+    pub deadline: Option<DateTime<Utc>>,
+    pub enqueued_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "RetryOverrides::is_empty")]
+    pub retry: RetryOverrides,
+    #[serde(default = "first_attempt")]
+    pub attempt: u32,
+    #[serde(default)]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub not_before_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_window: Option<MaintenanceWindow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renew_before: Option<RenewBefore>,
+}
+
+fn first_attempt() -> u32 {
+    1
+}
+
+impl From<&QueuedJob> for PersistedJob {
+    fn from(job: &QueuedJob) -> Self {
+        PersistedJob {
+            id: job.entry.id.clone(),
+            sans: job.entry.san_entries(),
+            dns_provider: job.entry.dns_provider.clone(),
+            kind: job.kind,
+            trigger: job.trigger,
+            persist: job.persist,
+            deadline: job.deadline,
+            enqueued_at: job.enqueued_at,
+            retry: job.entry.retry,
+            attempt: job.attempt,
+            not_before: job.not_before,
+            not_before_reason: job.not_before_reason.clone(),
+            maintenance_window: job.entry.maintenance_window.clone(),
+            renew_before: job.entry.renew_before,
+        }
+    }
+}
+
+pub enum Enqueued {
+    New(String),
+    /// A job for the same certificate was already queued or running.
+    Existing(String),
+}
+
+impl Enqueued {
+    pub fn job_id(&self) -> &str {
+        match self {
+            Enqueued::New(id) | Enqueued::Existing(id) => id,
+        }
+    }
+}
+
+/// Pending and running jobs, mirrored to `queue.json` so they survive a restart.
+pub struct JobQueue {
+    pending: Vec<QueuedJob>,
+    running: Vec<QueuedJob>,
+    file: StateFile,
+    closed: bool,
+}
+
+impl JobQueue {
+    pub fn new(dir_path: &Path) -> Self {
+        Self {
+            pending: Vec::new(),
+            running: Vec::new(),
+            file: state_file(dir_path),
+            closed: false,
+        }
+    }
+
+    /// Queues a run for `entry` unless one is already queued or running for
+    /// the same certificate. A duplicate can only move the existing job forward;
+    /// it never makes it persist, since a job that does not persist is for a
+    /// certificate config.toml already has.
+    pub fn push(
+        &mut self,
+        jobs: &mut JobStore,
+        entry: CertificateEntry,
+        kind: JobKind,
+        trigger: Trigger,
+        persist: bool,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Enqueued {
+        if let Some(job) = self.running.iter().find(|j| j.entry.id == entry.id) {
+            return Enqueued::Existing(job.job_id.clone());
+        }
+        if let Some(job) = self.pending.iter_mut().find(|j| j.entry.id == entry.id) {
+            if deadline.is_none_or(|d| job.deadline.is_some_and(|existing| d < existing)) {
+                job.deadline = deadline;
+            }
+            let id = job.job_id.clone();
+            self.save();
+            return Enqueued::Existing(id);
+        }
+
+        let job_id = jobs.create(&entry.id, kind, trigger);
+        self.pending.push(QueuedJob {
+            job_id: job_id.clone(),
+            entry,
+            kind,
+            trigger,
+            persist,
+            deadline,
+            enqueued_at: Utc::now(),
+            attempt: 1,
+            not_before: None,
+            not_before_reason: None,
+        });
+        self.save();
+        Enqueued::New(job_id)
+    }
+
+    /// Keeps a pending job from starting before `until`.
+    pub fn hold(&mut self, job_id: &str, until: DateTime<Utc>, reason: String) {
+        if let Some(job) = self.pending.iter_mut().find(|j| j.job_id == job_id) {
+            job.not_before = Some(until);
+            job.not_before_reason = Some(reason);
+            self.save();
+        }
+    }
+
+    /// Queues the next run of `failed`, to start no earlier than `not_before`.
+    /// Nothing is queued if another job for the certificate is already waiting.
+    pub fn retry(
+        &mut self,
+        jobs: &mut JobStore,
+        failed: QueuedJob,
+        not_before: DateTime<Utc>,
+        reason: String,
+    ) -> Option<String> {
+        if self.pending.iter().any(|j| j.entry.id == failed.entry.id) {
+            return None;
+        }
+        let job_id = jobs.create(&failed.entry.id, failed.kind, Trigger::Retry);
+        self.pending.push(QueuedJob {
+            job_id: job_id.clone(),
+            trigger: Trigger::Retry,
+            attempt: failed.attempt + 1,
+            not_before: Some(not_before),
+            not_before_reason: Some(reason),
+            enqueued_at: Utc::now(),
+            ..failed
+        });
+        self.save();
+        Some(job_id)
+    }
+
+    /// Lets a held job start right away, e.g. a retry waiting out its backoff.
+    /// Returns the job if it was held.
+    pub fn run_now(&mut self, job_id: &str) -> Option<QueuedJob> {
+        let job = self.pending.iter_mut().find(|j| j.job_id == job_id)?;
+        job.not_before.take()?;
+        job.not_before_reason = None;
+        let job = job.clone();
+        self.save();
+        Some(job)
+    }
+
+    /// Moves a pending job to running. Returns `None` if it is no longer
+    /// pending, e.g. because it was cancelled in the meantime.
+    pub fn take(&mut self, job_id: &str) -> Option<QueuedJob> {
+        let index = self.pending.iter().position(|j| j.job_id == job_id)?;
+        let job = self.pending.remove(index);
+        self.running.push(job.clone());
+        self.save();
+        Some(job)
+    }
+
+    pub fn finish(&mut self, job_id: &str) {
+        self.running.retain(|j| j.job_id != job_id);
+        self.save();
+    }
+
+    /// Drops a pending job. Returns `None` if it is not pending.
+    pub fn remove(&mut self, job_id: &str) -> Option<QueuedJob> {
+        let index = self.pending.iter().position(|j| j.job_id == job_id)?;
+        let job = self.pending.remove(index);
+        self.save();
+        Some(job)
+    }
+
+    /// How many pending jobs start before `job_id`.
+    pub fn jobs_ahead(&self, job_id: &str) -> Option<usize> {
+        let job = self.pending.iter().find(|j| j.job_id == job_id)?;
+        Some(
+            self.pending
+                .iter()
+                .filter(|other| other.priority() < job.priority())
+                .count(),
+        )
+    }
+
+    /// Re-queues the jobs a previous run left behind under new job ids,
+    /// keeping their priority. Returns the jobs that were restored.
+    pub fn restore(
+        &mut self,
+        jobs: &mut JobStore,
+        persisted: Vec<PersistedJob>,
+        allow_ip: bool,
+    ) -> Vec<QueuedJob> {
+        let mut restored = Vec::new();
+        for saved in persisted {
+            let mut entry = CertificateEntry {
+                retry: saved.retry,
+                maintenance_window: saved.maintenance_window,
+                renew_before: saved.renew_before,
+                ..CertificateEntry::new(saved.id, saved.sans, saved.dns_provider)
+            };
+            if let Err(e) = entry.validate(allow_ip) {
+                log(
+                    LogLevel::Warn,
+                    &format!("Dropping queued job that no longer validates: {}", e),
+                );
+                continue;
+            }
+            if self.pending.iter().any(|j| j.entry.id == entry.id) {
+                continue;
+            }
+            let job = QueuedJob {
+                job_id: jobs.create(&entry.id, saved.kind, saved.trigger),
+                entry,
+                kind: saved.kind,
+                trigger: saved.trigger,
+                persist: saved.persist,
+                deadline: saved.deadline,
+                enqueued_at: saved.enqueued_at,
+                attempt: saved.attempt,
+                not_before: saved.not_before,
+                not_before_reason: saved.not_before_reason,
+            };
+            self.pending.push(job.clone());
+            restored.push(job);
+        }
+        self.save();
+        restored
+    }
+
+    /// Pending jobs in the order they will run.
+    pub fn pending(&self) -> Vec<&QueuedJob> {
+        let mut pending: Vec<&QueuedJob> = self.pending.iter().collect();
+        pending.sort_by_key(|j| j.priority());
+        pending
+    }
+
+    pub fn running(&self) -> &[QueuedJob] {
+        &self.running
+    }
+
+    /// Saves the queue one last time, including running jobs, so that they
+    /// are picked up again after a restart. Later changes are not written.
+    pub fn close(&mut self) {
+        self.save();
+        self.closed = true;
+    }
+
+    fn save(&self) {
+        if self.closed {
+            return;
+        }
+        let persisted: Vec<PersistedJob> = self
+            .running
+            .iter()
+            .chain(self.pending.iter())
+            .map(PersistedJob::from)
+            .collect();
+        self.file.save(&persisted);
+    }
+}
+
+/// Estimated seconds until a job with `jobs_ahead` jobs running or queued
+/// before it starts,
+/// with up to `parallel` jobs running at once.
+pub fn eta_secs(jobs_ahead: usize, average: Option<chrono::Duration>, parallel: usize) -> i64 {
+    let per_job = average
+        .map(|d| d.num_seconds().max(1))
+        .unwrap_or(DEFAULT_JOB_SECS);
+    per_job * (jobs_ahead / parallel.max(1)) as i64
+}
+
+fn state_file(dir_path: &Path) -> StateFile {
+    StateFile::new(dir_path.join(QUEUE_FILE), "job queue")
+}
+
+/// Reads the jobs a previous run left in `queue.json`.
+pub async fn load(dir_path: &Path) -> Vec<PersistedJob> {
+    state_file(dir_path).load().await
+}
@@ -0,0 +1,44 @@
+/* src/redact.rs */
+
+/// Name segments that mark a variable as secret when they end its name,
+/// e.g. `API_KEY` or `CLOUDFLARE_DNS_API_TOKEN`.
+const SECRET_SUFFIXES: [&str; 9] = [
+    "KEY",
+    "APIKEY",
+    "TOKEN",
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "PASS",
+    "PWD",
+    "CREDENTIALS",
+];
+/// Segments that mark a variable as secret wherever they appear,
+/// e.g. `AWS_SECRET_ACCESS_KEY_ID`.
+const SECRET_SEGMENTS: [&str; 4] = ["TOKEN", "SECRET", "PASSWORD", "PASSWD"];
+
+/// Whether a variable name looks like it holds a credential.
+/// `KEY_TYPE` is not secret, `API_KEY` is.
+pub fn is_secret_name(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    let segments: Vec<&str> = upper
+        .split(['_', '-', '.'])
+        .filter(|s| !s.is_empty())
+        .collect();
+    segments
+        .last()
+        .is_some_and(|last| SECRET_SUFFIXES.contains(last))
+        || segments.iter().any(|s| SECRET_SEGMENTS.contains(s))
+}
+
+/// Replaces every occurrence of each secret in `text` with `***`.
+/// Longer secrets go first so one that contains another is fully masked.
+pub fn redact(text: &str, secrets: &[String]) -> String {
+    let mut secrets: Vec<&String> = secrets.iter().filter(|s| !s.is_empty()).collect();
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    let mut text = text.to_string();
+    for secret in secrets {
+        text = text.replace(secret.as_str(), "***");
+    }
+    text
+}
@@ -0,0 +1,366 @@
+/* src/renewal.rs */
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// Without `renew_before`, renewals start this long before expiry, or with a
+/// third of the lifetime left for certificates shorter than 90 days.
+const RENEW_BEFORE_DAYS: i64 = 30;
+/// Lifetime assumed for a certificate that has not been issued yet.
+const DEFAULT_LIFETIME_DAYS: i64 = 90;
+
+/// How long before expiry a certificate renews: a duration (`"30d"`, `"12h"`)
+/// or the fraction of its lifetime still left (`"1/3"`, `"25%"`).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum RenewBefore {
+    Duration(Duration),
+    Fraction(f64),
+}
+
+impl TryFrom<String> for RenewBefore {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        Self::parse(&value)
+    }
+}
+
+impl From<RenewBefore> for String {
+    fn from(value: RenewBefore) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for RenewBefore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenewBefore::Duration(duration) => write!(f, "{}s", duration.num_seconds()),
+            RenewBefore::Fraction(fraction) => write!(f, "{}%", fraction * 100.0),
+        }
+    }
+}
+
+impl RenewBefore {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let invalid = || {
+            format!(
+                "invalid renew_before '{}': expected a duration like '30d' or a fraction like '1/3'",
+                value
+            )
+        };
+
+        let fraction = if let Some(percent) = value.strip_suffix('%') {
+            Some(percent.trim().parse::<f64>().map_err(|_| invalid())? / 100.0)
+        } else if let Some((num, den)) = value.split_once('/') {
+            let num = num.trim().parse::<f64>().map_err(|_| invalid())?;
+            let den = den.trim().parse::<f64>().map_err(|_| invalid())?;
+            Some(num / den)
+        } else {
+            None
+        };
+        if let Some(fraction) = fraction {
+            if !(fraction > 0.0 && fraction < 1.0) {
+                return Err(format!(
+                    "invalid renew_before '{}': a fraction must be between 0 and 1",
+                    value
+                ));
+            }
+            return Ok(RenewBefore::Fraction(fraction));
+        }
+
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let amount = value[..split].parse::<i64>().map_err(|_| invalid())?;
+        let duration = match value[split..].trim() {
+            "s" => Duration::seconds(amount),
+            "m" => Duration::minutes(amount),
+            "h" => Duration::hours(amount),
+            "d" => Duration::days(amount),
+            "w" => Duration::weeks(amount),
+            _ => return Err(invalid()),
+        };
+        if duration <= Duration::zero() {
+            return Err(format!(
+                "invalid renew_before '{}': must be positive",
+                value
+            ));
+        }
+        Ok(RenewBefore::Duration(duration))
+    }
+
+    /// How long before `not_after` to renew. A duration is capped at half
+    /// the lifetime, so a fresh certificate is never due right away.
+    fn threshold(&self, lifetime: Duration) -> Duration {
+        match self {
+            RenewBefore::Duration(duration) => (*duration).min(lifetime / 2),
+            RenewBefore::Fraction(fraction) => {
+                Duration::seconds((lifetime.num_seconds() as f64 * fraction) as i64)
+            }
+        }
+    }
+}
+
+fn threshold(renew_before: Option<RenewBefore>, lifetime: Duration) -> Duration {
+    match renew_before {
+        Some(renew_before) => renew_before.threshold(lifetime),
+        None => Duration::days(RENEW_BEFORE_DAYS).min(lifetime / 3),
+    }
+}
+
+/// Whole days for lego's `renew --days`, rounded up so lego agrees that a
+/// certificate at its renewal time is due. `renewal` is `None` before the
+/// certificate is first issued.
+pub fn renew_days(renewal: Option<ScheduledRenewal>, renew_before: Option<RenewBefore>) -> i64 {
+    let threshold = match renewal {
+        Some(renewal) => renewal.expires_at - renewal.renew_at,
+        None => threshold(renew_before, Duration::days(DEFAULT_LIFETIME_DAYS)),
+    };
+    let day = Duration::days(1).num_seconds();
+    ((threshold.num_seconds() + day - 1) / day).max(1)
+}
+
+/// When an issued certificate is due for renewal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledRenewal {
+    pub renew_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ScheduledRenewal {
+    pub fn new(
+        not_before: DateTime<Utc>,
+        not_after: DateTime<Utc>,
+        renew_before: Option<RenewBefore>,
+    ) -> Self {
+        Self {
+            renew_at: not_after - threshold(renew_before, not_after - not_before),
+            expires_at: not_after,
+        }
+    }
+}
+
+/// The renewal instant of every issued certificate, ordered by time.
+#[derive(Default)]
+pub struct RenewalQueue {
+    order: BTreeSet<(DateTime<Utc>, String)>,
+    scheduled: HashMap<String, ScheduledRenewal>,
+    /// Renewals lego declined: the expiry of the certificate it kept and
+    /// when to try again.
+    postponed: HashMap<String, (DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl RenewalQueue {
+    pub fn set(&mut self, id: &str, mut renewal: ScheduledRenewal) {
+        self.remove(id);
+        if let Some((expires_at, until)) = self.postponed.get(id).copied() {
+            if expires_at == renewal.expires_at {
+                renewal.renew_at = renewal.renew_at.max(until);
+            } else {
+                self.postponed.remove(id);
+            }
+        }
+        self.order.insert((renewal.renew_at, id.to_string()));
+        self.scheduled.insert(id.to_string(), renewal);
+    }
+
+    /// Schedules a certificate lego declined to renew for `until` instead,
+    /// for as long as the certificate stays the same.
+    pub fn postpone(&mut self, id: &str, renewal: ScheduledRenewal, until: DateTime<Utc>) {
+        self.postponed
+            .insert(id.to_string(), (renewal.expires_at, until));
+        self.set(id, renewal);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if let Some(old) = self.scheduled.remove(id) {
+            self.order.remove(&(old.renew_at, id.to_string()));
+        }
+    }
+
+    /// Drops certificates that are no longer configured.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let gone: Vec<String> = self
+            .scheduled
+            .keys()
+            .filter(|id| !keep(id))
+            .cloned()
+            .collect();
+        for id in gone {
+            self.remove(&id);
+        }
+        self.postponed.retain(|id, _| keep(id));
+    }
+
+    pub fn get(&self, id: &str) -> Option<ScheduledRenewal> {
+        self.scheduled.get(id).copied()
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.order.first().map(|(at, _)| *at)
+    }
+
+    /// Removes and returns the certificates due at `now`. They are scheduled
+    /// again once their renewal issues a new certificate.
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<(String, ScheduledRenewal)> {
+        let mut due = Vec::new();
+        while let Some((at, id)) = self.order.first().cloned() {
+            if at > now {
+                break;
+            }
+            self.order.pop_first();
+            if let Some(renewal) = self.scheduled.remove(&id) {
+                due.push((id, renewal));
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn renewal(lifetime_days: i64, renew_before: Option<&str>) -> ScheduledRenewal {
+        let renew_before = renew_before.map(|v| RenewBefore::parse(v).unwrap());
+        ScheduledRenewal::new(
+            start(),
+            start() + Duration::days(lifetime_days),
+            renew_before,
+        )
+    }
+
+    fn left(renewal: ScheduledRenewal) -> Duration {
+        renewal.expires_at - renewal.renew_at
+    }
+
+    #[test]
+    fn parses_durations_and_fractions() {
+        let cases = [
+            ("30d", RenewBefore::Duration(Duration::days(30))),
+            (" 36h ", RenewBefore::Duration(Duration::hours(36))),
+            ("2w", RenewBefore::Duration(Duration::weeks(2))),
+            ("90m", RenewBefore::Duration(Duration::minutes(90))),
+            ("45s", RenewBefore::Duration(Duration::seconds(45))),
+            ("1/3", RenewBefore::Fraction(1.0 / 3.0)),
+            ("1 / 4", RenewBefore::Fraction(0.25)),
+            ("25%", RenewBefore::Fraction(0.25)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(RenewBefore::parse(input), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for input in [
+            "", "0d", "0s", "d", "30", "30x", "-1d", "1.5d", "0/3", "3/3", "1/0", "0%", "100%",
+            "x%", "1/x",
+        ] {
+            assert!(RenewBefore::parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for input in ["30d", "36h", "1/3", "25%"] {
+            let value = RenewBefore::parse(input).unwrap();
+            let parsed = RenewBefore::parse(&value.to_string()).unwrap();
+            match (value, parsed) {
+                (RenewBefore::Fraction(a), RenewBefore::Fraction(b)) => {
+                    assert!((a - b).abs() < 1e-12, "{}", input)
+                }
+                _ => assert_eq!(parsed, value, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn default_is_30_days_or_a_third_of_short_lifetimes() {
+        assert_eq!(left(renewal(90, None)), Duration::days(30));
+        assert_eq!(left(renewal(365, None)), Duration::days(30));
+        assert_eq!(left(renewal(6, None)), Duration::days(2));
+    }
+
+    #[test]
+    fn durations_are_capped_at_half_the_lifetime() {
+        assert_eq!(left(renewal(90, Some("10d"))), Duration::days(10));
+        assert_eq!(left(renewal(90, Some("60d"))), Duration::days(45));
+        assert_eq!(left(renewal(6, Some("30d"))), Duration::days(3));
+    }
+
+    #[test]
+    fn fractions_scale_with_the_lifetime() {
+        assert_eq!(left(renewal(90, Some("1/3"))), Duration::days(30));
+        assert_eq!(left(renewal(20, Some("50%"))), Duration::days(10));
+        let short = renewal(6, Some("1/2"));
+        assert_eq!(short.renew_at, start() + Duration::days(3));
+    }
+
+    #[test]
+    fn renew_days_rounds_up() {
+        assert_eq!(renew_days(Some(renewal(90, None)), None), 30);
+        assert_eq!(renew_days(Some(renewal(90, Some("36h"))), None), 2);
+        assert_eq!(renew_days(Some(renewal(90, Some("1s"))), None), 1);
+        // Before the first issuance a 90-day lifetime is assumed.
+        assert_eq!(renew_days(None, None), 30);
+        let half = RenewBefore::parse("1/2").ok();
+        assert_eq!(renew_days(None, half), 45);
+    }
+
+    #[test]
+    fn queue_hands_out_due_renewals_in_order() {
+        let at = |days: i64| ScheduledRenewal {
+            renew_at: start() + Duration::days(days),
+            expires_at: start() + Duration::days(days + 30),
+        };
+        let mut queue = RenewalQueue::default();
+        queue.set("b", at(2));
+        queue.set("a", at(1));
+        queue.set("c", at(5));
+        queue.set("b", at(3));
+        assert_eq!(queue.next_due(), Some(at(1).renew_at));
+
+        let due = queue.take_due(start() + Duration::days(3));
+        let ids: Vec<&str> = due.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(queue.get("b"), None);
+        assert_eq!(queue.next_due(), Some(at(5).renew_at));
+
+        queue.retain(|id| id != "c");
+        assert_eq!(queue.next_due(), None);
+    }
+
+    #[test]
+    fn postponed_renewals_wait_until_the_certificate_changes() {
+        let due = ScheduledRenewal {
+            renew_at: start(),
+            expires_at: start() + Duration::days(30),
+        };
+        let until = start() + Duration::days(1);
+        let mut queue = RenewalQueue::default();
+        queue.postpone("a", due, until);
+        assert_eq!(queue.next_due(), Some(until));
+
+        // A rescan schedules the same certificate again.
+        queue.set("a", due);
+        assert!(queue.take_due(start()).is_empty());
+        assert_eq!(queue.next_due(), Some(until));
+
+        let renewed = ScheduledRenewal {
+            renew_at: start() + Duration::hours(1),
+            expires_at: start() + Duration::days(90),
+        };
+        queue.set("a", renewed);
+        assert_eq!(queue.next_due(), Some(renewed.renew_at));
+    }
+}
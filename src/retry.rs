@@ -0,0 +1,149 @@
+/* src/retry.rs */
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How failed acquisitions and renewals are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Runs in total, counting the first; 1 disables retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Each delay is moved by up to this fraction in either direction.
+    pub jitter: f64,
+}
+
+/// Per-certificate overrides of the global policy, e.g.
+/// `retry = { max_attempts = 3, base_delay = 60 }` in `config.toml`.
+/// Delays are in seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_delay: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_delay: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter: Option<f64>,
+}
+
+impl RetryOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl RetryPolicy {
+    pub fn with(&self, overrides: &RetryOverrides) -> Self {
+        Self {
+            max_attempts: overrides.max_attempts.unwrap_or(self.max_attempts).max(1),
+            base_delay: overrides
+                .base_delay
+                .map(Duration::from_secs)
+                .unwrap_or(self.base_delay),
+            max_delay: overrides
+                .max_delay
+                .map(Duration::from_secs)
+                .unwrap_or(self.max_delay),
+            jitter: overrides.jitter.unwrap_or(self.jitter).clamp(0.0, 1.0),
+        }
+    }
+
+    /// The delay before the run after failed run number `attempt` (1-based):
+    /// `base_delay * 2^(attempt - 1)`, capped at `max_delay`, with jitter.
+    /// `None` once `max_attempts` runs have failed.
+    pub fn delay_after(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let offset = self.jitter * (2.0 * unit_random() - 1.0);
+        Some(delay.mul_f64(1.0 + offset))
+    }
+
+    pub fn next_attempt_at(&self, attempt: u32) -> Option<DateTime<Utc>> {
+        let delay = chrono::Duration::from_std(self.delay_after(attempt)?).ok()?;
+        Some(Utc::now() + delay)
+    }
+}
+
+/// A value in `[0, 1)` that is random enough to spread retries and checks apart.
+pub fn unit_random() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_i64(Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(300),
+            max_delay: Duration::from_secs(3600),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let delays: Vec<Option<u64>> = (1..=5)
+            .map(|attempt| policy(0.0).delay_after(attempt).map(|d| d.as_secs()))
+            .collect();
+        assert_eq!(delays, [Some(300), Some(600), Some(1200), Some(2400), None]);
+        let long = RetryPolicy {
+            max_attempts: 100,
+            ..policy(0.0)
+        };
+        assert_eq!(long.delay_after(5), Some(Duration::from_secs(3600)));
+        assert_eq!(long.delay_after(99), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_fraction() {
+        for _ in 0..100 {
+            let delay = policy(0.2).delay_after(1).unwrap().as_secs_f64();
+            assert!((240.0..=360.0).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn one_attempt_disables_retries() {
+        let once = policy(0.0).with(&RetryOverrides {
+            max_attempts: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(once.delay_after(1), None);
+        assert_eq!(once.next_attempt_at(1), None);
+    }
+
+    #[test]
+    fn overrides_replace_only_what_they_set() {
+        let overridden = policy(0.2).with(&RetryOverrides {
+            max_attempts: Some(0),
+            base_delay: Some(60),
+            jitter: Some(5.0),
+            ..Default::default()
+        });
+        assert_eq!(overridden.max_attempts, 1);
+        assert_eq!(overridden.base_delay, Duration::from_secs(60));
+        assert_eq!(overridden.max_delay, Duration::from_secs(3600));
+        assert_eq!(overridden.jitter, 1.0);
+        assert!(RetryOverrides::default().is_empty());
+    }
+
+    #[test]
+    fn unit_random_is_in_range() {
+        for _ in 0..100 {
+            assert!((0.0..1.0).contains(&unit_random()));
+        }
+    }
+}
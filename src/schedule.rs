@@ -0,0 +1,179 @@
+/* src/schedule.rs */
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// A cron expression evaluated in the server's local time zone. Five fields
+/// (`min hour day month weekday`) or six, with seconds first. Weekdays are
+/// numbered as in crontab: 0 or 7 is Sunday, 1 is Monday.
+#[derive(Debug, Clone)]
+pub struct CronSchedule(cron::Schedule);
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let invalid = |e: String| format!("invalid cron expression '{}': {}", expr, e);
+        let mut fields: Vec<String> = expr.split_whitespace().map(str::to_string).collect();
+        if fields.len() == 5 {
+            fields.insert(0, "0".to_string());
+        }
+        if let Some(weekdays) = fields.get_mut(5) {
+            *weekdays = crate_weekdays(weekdays).map_err(invalid)?;
+        }
+        cron::Schedule::from_str(&fields.join(" "))
+            .map(Self)
+            .map_err(|e| invalid(e.to_string()))
+    }
+
+    /// The first time strictly after `time`.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = time.with_timezone(&Local);
+        self.0.after(&local).next().map(|t| t.with_timezone(&Utc))
+    }
+
+    /// The last time at or before `time`.
+    fn last_before(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = time.with_timezone(&Local);
+        if self.0.includes(local) {
+            return Some(time);
+        }
+        self.0
+            .after(&local)
+            .next_back()
+            .map(|t| t.with_timezone(&Utc))
+    }
+}
+
+/// Rewrites a crontab weekday field into the numbering of the `cron` crate,
+/// which counts from 1 for Sunday. Named days are passed through.
+fn crate_weekdays(field: &str) -> Result<String, String> {
+    let invalid = || format!("invalid weekday '{}': expected 0-7 or a day name", field);
+    let day = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|d| *d <= 7)
+            .ok_or_else(invalid)
+    };
+
+    let mut named = Vec::new();
+    let mut days = BTreeSet::new();
+    for item in field.split(',') {
+        let (base, step) = match item.split_once('/') {
+            Some((base, step)) => (base, Some(step.parse::<usize>().map_err(|_| invalid())?)),
+            None => (item, None),
+        };
+        if !base.contains(|c: char| c.is_ascii_digit()) && (base != "*" || step.is_none()) {
+            named.push(item.to_string());
+            continue;
+        }
+        let (first, last) = match base.split_once('-') {
+            _ if base == "*" => (0, 6),
+            Some((first, last)) => (day(first)?, day(last)?),
+            None if step.is_some() => (day(base)?, 7),
+            None => (day(base)?, day(base)?),
+        };
+        if first > last || step == Some(0) {
+            return Err(invalid());
+        }
+        days.extend((first..=last).step_by(step.unwrap_or(1)).map(|d| d % 7 + 1));
+    }
+    named.extend(days.iter().map(u32::to_string));
+    Ok(named.join(","))
+}
+
+/// When renewals of a certificate may run: `duration` minutes from each time
+/// `schedule` fires, e.g. `{ schedule = "0 2 * * *", duration = 120 }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceWindow {
+    pub schedule: String,
+    pub duration: u64,
+}
+
+impl MaintenanceWindow {
+    pub fn validate(&self) -> Result<(), String> {
+        CronSchedule::parse(&self.schedule)?;
+        if self.duration == 0 {
+            return Err("maintenance window duration must be at least one minute".to_string());
+        }
+        Ok(())
+    }
+
+    /// `time` itself if it falls inside the window, otherwise the next time
+    /// the window opens. `None` for a window that does not parse or never opens.
+    pub fn next_open(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let schedule = CronSchedule::parse(&self.schedule).ok()?;
+        let length = chrono::Duration::minutes(self.duration as i64);
+        match schedule.last_before(time) {
+            Some(start) if time < start + length => Some(time),
+            _ => schedule.next_after(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Duration, TimeZone, Weekday};
+
+    fn weekdays(expr: &str) -> Vec<Weekday> {
+        let schedule = CronSchedule::parse(expr).unwrap();
+        let mut time = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let mut days = Vec::new();
+        for _ in 0..7 {
+            time = schedule.next_after(time).unwrap();
+            days.push(time.with_timezone(&Local).weekday());
+        }
+        days.sort_by_key(|d| d.num_days_from_monday());
+        days.dedup();
+        days
+    }
+
+    #[test]
+    fn weekdays_are_numbered_like_crontab() {
+        use Weekday::*;
+        assert_eq!(weekdays("0 2 * * 1-5"), [Mon, Tue, Wed, Thu, Fri]);
+        assert_eq!(weekdays("0 2 * * 0"), [Sun]);
+        assert_eq!(weekdays("0 2 * * 7"), [Sun]);
+        assert_eq!(weekdays("0 2 * * 5-7"), [Fri, Sat, Sun]);
+        assert_eq!(weekdays("0 2 * * 1,3"), [Mon, Wed]);
+        assert_eq!(weekdays("0 2 * * */2"), [Tue, Thu, Sat, Sun]);
+        assert_eq!(weekdays("0 0 2 * * 6"), [Sat]);
+        assert_eq!(weekdays("0 2 * * Mon-Fri"), [Mon, Tue, Wed, Thu, Fri]);
+    }
+
+    #[test]
+    fn crate_weekdays_translates_numbers_only() {
+        assert_eq!(crate_weekdays("*").unwrap(), "*");
+        assert_eq!(crate_weekdays("?").unwrap(), "?");
+        assert_eq!(crate_weekdays("Sat,0").unwrap(), "Sat,1");
+        assert_eq!(crate_weekdays("1-5").unwrap(), "2,3,4,5,6");
+        assert_eq!(crate_weekdays("3/2").unwrap(), "1,4,6");
+    }
+
+    #[test]
+    fn invalid_weekdays_are_rejected() {
+        for expr in ["0 2 * * 8", "0 2 * * 5-1", "0 2 * * 1/0", "0 2 * * 1-x"] {
+            assert!(CronSchedule::parse(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn window_is_open_for_its_duration() {
+        let window = MaintenanceWindow {
+            schedule: "0 * * * *".to_string(),
+            duration: 10,
+        };
+        let hour = CronSchedule::parse(&window.schedule)
+            .unwrap()
+            .next_after(Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap())
+            .unwrap();
+        let inside = hour + Duration::minutes(5);
+        assert_eq!(window.next_open(inside), Some(inside));
+        let outside = hour + Duration::minutes(30);
+        assert_eq!(window.next_open(outside), Some(hour + Duration::hours(1)));
+    }
+}
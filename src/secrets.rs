@@ -0,0 +1,134 @@
+/* src/secrets.rs */
+
+use crate::config::DnsProviderConfig;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::{fs, process::Command, time};
+
+const SECRET_ENV_PREFIX: &str = "LAZY_ACME_SECRET_";
+/// How long an `exec` source may take, so a command waiting for a passphrase
+/// cannot hold up a job or startup.
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a provider variable's value comes from instead of the config file:
+/// `{ env = "CF_TOKEN" }`, `{ file = "/run/secrets/cf" }` or `{ exec = ["pass", "cf"] }`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum SecretSource {
+    Env(String),
+    File(PathBuf),
+    Exec(Vec<String>),
+}
+
+impl SecretSource {
+    pub fn from_value(value: &toml::Value) -> Option<Self> {
+        match value {
+            toml::Value::Table(_) => value.clone().try_into().ok(),
+            _ => None,
+        }
+    }
+
+    async fn read(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let value = match self {
+            SecretSource::Env(name) => std::env::var(name)
+                .map_err(|_| format!("environment variable '{}' is not set", name))?,
+            SecretSource::File(path) => fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("cannot read {:?}: {}", path, e))?,
+            SecretSource::Exec(argv) => {
+                let (program, args) = argv.split_first().ok_or("'exec' needs a command")?;
+                let output = Command::new(program)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .kill_on_drop(true)
+                    .output();
+                let output = time::timeout(EXEC_TIMEOUT, output)
+                    .await
+                    .map_err(|_| {
+                        format!(
+                            "'{}' did not finish within {}s",
+                            program,
+                            EXEC_TIMEOUT.as_secs()
+                        )
+                    })?
+                    .map_err(|e| format!("failed to start '{}': {}", program, e))?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "'{}' exited with {}: {}",
+                        program,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )
+                    .into());
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|_| format!("'{}' printed a non-UTF-8 value", program))?
+            }
+        };
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+}
+
+/// The environment variable a resolved secret travels in from the daemon to
+/// lego and its hooks, e.g. `LAZY_ACME_SECRET_CLOUDFLARE_API_TOKEN`.
+pub fn env_name(provider: &str, var: &str) -> String {
+    format!("{}{}_{}", SECRET_ENV_PREFIX, provider, var)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The provider name of a `<name>.dns.toml` path.
+pub fn provider_name(config_path: &Path) -> String {
+    let file_name = config_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    file_name
+        .strip_suffix(".dns.toml")
+        .unwrap_or(&file_name)
+        .to_string()
+}
+
+/// Resolves every secret-source variable of `provider`'s config into
+/// `provider_config.resolved`. A value the daemon already passed down through
+/// the environment is used as is, so hooks never resolve a source twice.
+pub async fn resolve(
+    provider: &str,
+    provider_config: &mut DnsProviderConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let sources: Vec<(String, SecretSource)> = provider_config
+        .vars
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), SecretSource::from_value(value)?)))
+        .collect();
+    for (name, source) in sources {
+        let value = match std::env::var(env_name(provider, &name)) {
+            Ok(value) => value,
+            Err(_) => source
+                .read()
+                .await
+                .map_err(|e| format!("Secret '{}' of provider '{}': {}", name, provider, e))?,
+        };
+        provider_config.resolved.insert(name, value);
+    }
+    Ok(())
+}
+
+/// The environment entries that hand `provider`'s resolved secrets to a child.
+pub fn env_for(provider: &str, provider_config: &DnsProviderConfig) -> BTreeMap<String, String> {
+    provider_config
+        .resolved
+        .iter()
+        .map(|(name, value)| (env_name(provider, name), value.clone()))
+        .collect()
+}
@@ -19,6 +19,21 @@ pub async fn run_server(app_state: AppState) -> Result<(), Box<dyn std::error::E
             "/v1/certificate/{domain}/key",
             get(handlers::get_certificate_key),
         )
+        .route(
+            "/v1/certificate/{domain}/disable",
+            post(handlers::disable_certificate),
+        )
+        .route(
+            "/v1/certificate/{domain}/enable",
+            post(handlers::enable_certificate),
+        )
+        .route("/v1/pause", post(handlers::pause_scheduler))
+        .route("/v1/resume", post(handlers::resume_scheduler))
+        .route("/v1/queue", get(handlers::get_queue))
+        .route("/v1/history", get(handlers::get_history))
+        .route("/v1/jobs/{id}", get(handlers::get_job))
+        .route("/v1/jobs/{id}/log", get(handlers::get_job_log))
+        .route("/v1/jobs/{id}/cancel", post(handlers::cancel_job))
         .with_state(app_state.clone());
 
     let addr = SocketAddr::from(([0, 0, 0, 0], app_state.config.bind_port));
@@ -1,32 +1,90 @@
 /* src/state.rs */
 
-use crate::config::AppConfig;
+use crate::budget::Budget;
+use crate::config::{AppConfig, CertificateEntry};
+use crate::history::History;
+use crate::jobs::JobStore;
+use crate::metadata::CertificateMetadata;
+use crate::pause::Holds;
+use crate::queue::JobQueue;
+use crate::renewal::RenewalQueue;
+use crate::status::DomainStore;
+use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::Notify;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
 pub enum DomainStatus {
+    Queued,
     Acquiring,
     Ready,
-    Failed(String),
+    Failed {
+        reason: String,
+        /// Failed runs in a row.
+        attempts: u32,
+        /// When the next retry is queued to run; `None` once retries are used up.
+        next_retry_at: Option<DateTime<Utc>>,
+    },
+}
+
+impl DomainStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainStatus::Queued => "queued",
+            DomainStatus::Acquiring => "acquiring",
+            DomainStatus::Ready => "ready",
+            DomainStatus::Failed { .. } => "failed",
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<AppConfig>,
     pub task_running: Arc<RwLock<bool>>,
-    pub domains: Arc<RwLock<HashMap<String, DomainStatus>>>,
-    pub is_acquiring: Arc<RwLock<bool>>,
+    pub next_renewal_check: Arc<RwLock<Option<DateTime<Utc>>>>,
+    pub domains: Arc<RwLock<DomainStore>>,
+    pub certificates: Arc<RwLock<HashMap<String, CertificateEntry>>>,
+    /// lego's resource file for each certificate id that has been issued.
+    pub metadata: Arc<RwLock<HashMap<String, CertificateMetadata>>>,
+    pub jobs: Arc<RwLock<JobStore>>,
+    pub queue: Arc<RwLock<JobQueue>>,
+    /// Wakes the queue worker when a job is pushed or finishes.
+    pub queue_notify: Arc<Notify>,
+    pub budget: Arc<RwLock<Budget>>,
+    pub renewals: Arc<RwLock<RenewalQueue>>,
+    /// Wakes the renewal scheduler when a certificate's renewal time changes.
+    pub renewal_notify: Arc<Notify>,
+    pub history: History,
+    pub holds: Arc<RwLock<Holds>>,
 }
 
 impl AppState {
     pub fn new(config: AppConfig) -> Self {
+        let queue = JobQueue::new(&config.dir_path);
+        let budget = Budget::new(&config);
+        let history = History::new(&config.dir_path, config.history_max_bytes);
+        let domains = DomainStore::new(&config.dir_path);
+        let holds = Holds::new(&config.dir_path);
         Self {
             config: Arc::new(config),
             task_running: Arc::new(RwLock::new(false)),
-            domains: Arc::new(RwLock::new(HashMap::new())),
-            is_acquiring: Arc::new(RwLock::new(false)),
+            next_renewal_check: Arc::new(RwLock::new(None)),
+            domains: Arc::new(RwLock::new(domains)),
+            certificates: Arc::new(RwLock::new(HashMap::new())),
+            metadata: Arc::new(RwLock::new(HashMap::new())),
+            jobs: Arc::new(RwLock::new(JobStore::default())),
+            queue: Arc::new(RwLock::new(queue)),
+            queue_notify: Arc::new(Notify::new()),
+            budget: Arc::new(RwLock::new(budget)),
+            renewals: Arc::new(RwLock::new(RenewalQueue::default())),
+            renewal_notify: Arc::new(Notify::new()),
+            history,
+            holds: Arc::new(RwLock::new(holds)),
         }
     }
 }
@@ -0,0 +1,113 @@
+/* src/status.rs */
+
+use crate::persist::StateFile;
+use crate::state::DomainStatus;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const STATUS_FILE: &str = "status.json";
+
+/// A certificate's status and when it last changed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DomainRecord {
+    pub status: DomainStatus,
+    pub since: DateTime<Utc>,
+    /// When lego last started for this certificate.
+    #[serde(default)]
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    /// The daemon stopped while lego was running; the run is queued again.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+/// The status of each certificate, mirrored to `status.json` on every change
+/// so failures and interrupted runs are still known after a restart.
+pub struct DomainStore {
+    records: HashMap<String, DomainRecord>,
+    file: StateFile,
+    closed: bool,
+}
+
+impl DomainStore {
+    pub fn new(dir_path: &Path) -> Self {
+        Self {
+            records: HashMap::new(),
+            file: state_file(dir_path),
+            closed: false,
+        }
+    }
+
+    /// Takes over the statuses a previous run saved. Runs that were still in
+    /// progress are marked interrupted and counted as queued again. Returns
+    /// their ids.
+    pub fn restore(&mut self, saved: HashMap<String, DomainRecord>) -> Vec<String> {
+        let mut interrupted = Vec::new();
+        for (id, mut record) in saved {
+            if matches!(record.status, DomainStatus::Acquiring) {
+                record.status = DomainStatus::Queued;
+                record.interrupted = true;
+                interrupted.push(id.clone());
+            }
+            self.records.insert(id, record);
+        }
+        self.save();
+        interrupted
+    }
+
+    pub fn get(&self, id: &str) -> Option<&DomainStatus> {
+        self.records.get(id).map(|record| &record.status)
+    }
+
+    pub fn record(&self, id: &str) -> Option<&DomainRecord> {
+        self.records.get(id)
+    }
+
+    pub fn set(&mut self, id: &str, status: DomainStatus) {
+        let now = Utc::now();
+        let last_attempt_at = match status {
+            DomainStatus::Acquiring => Some(now),
+            _ => self.records.get(id).and_then(|r| r.last_attempt_at),
+        };
+        self.records.insert(
+            id.to_string(),
+            DomainRecord {
+                status,
+                since: now,
+                last_attempt_at,
+                interrupted: false,
+            },
+        );
+        self.save();
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if self.records.remove(id).is_some() {
+            self.save();
+        }
+    }
+
+    /// Saves the statuses one last time. Later changes, such as runs being
+    /// cancelled on shutdown, are not written, so those runs read as
+    /// interrupted on the next start.
+    pub fn close(&mut self) {
+        self.save();
+        self.closed = true;
+    }
+
+    fn save(&self) {
+        if !self.closed {
+            self.file.save(&self.records);
+        }
+    }
+}
+
+fn state_file(dir_path: &Path) -> StateFile {
+    StateFile::new(dir_path.join(STATUS_FILE), "domain status")
+}
+
+/// Reads the statuses a previous run left in `status.json`.
+pub async fn load(dir_path: &Path) -> HashMap<String, DomainRecord> {
+    state_file(dir_path).load().await
+}
@@ -2,145 +2,787 @@
 
 use crate::{
     acme::{self, CommandType},
-    config,
+    budget::{Blocked, JobProfile},
+    config::{self, AppConfig, CertificateEntry},
+    history::{self, HistoryEntry},
+    jobs::{self, JobKind, JobStatus, Trigger},
+    lego,
+    pause::Hold,
+    queue::{self, Enqueued, QueuedJob},
+    renewal::ScheduledRenewal,
+    retry,
     state::{AppState, DomainStatus},
 };
+use chrono::{DateTime, Utc};
 use fancy_log::{LogLevel, log};
 use tokio::time;
 
-pub fn spawn_startup_check_task(app_state: AppState) {
+/// How often the renewal scheduler looks for edits to `config.toml`.
+const CONFIG_POLL_INTERVAL: chrono::Duration = chrono::Duration::seconds(30);
+
+/// Queues a lego run for `entry` and wakes the worker. A certificate that
+/// already has a job queued or running gets that job back instead.
+pub fn enqueue(
+    app_state: &AppState,
+    entry: CertificateEntry,
+    kind: JobKind,
+    trigger: Trigger,
+    persist: bool,
+    deadline: Option<DateTime<Utc>>,
+) -> Enqueued {
+    let id = entry.id.clone();
+    let enqueued = {
+        let mut jobs = app_state.jobs.write();
+        app_state
+            .queue
+            .write()
+            .push(&mut jobs, entry.clone(), kind, trigger, persist, deadline)
+    };
+    if let Enqueued::New(job_id) = &enqueued {
+        app_state.certificates.write().insert(id.clone(), entry);
+        mark_queued(app_state, &id, kind);
+        log(
+            LogLevel::Info,
+            &format!("Queued job {} for '{}'.", job_id, id),
+        );
+        app_state.queue_notify.notify_one();
+    }
+    enqueued
+}
+
+/// Starts a retry that is waiting out its backoff as soon as the budget allows.
+pub fn expedite(app_state: &AppState, job_id: &str) {
+    let Some(job) = app_state.queue.write().run_now(job_id) else {
+        return;
+    };
+    mark_queued(app_state, &job.entry.id, job.kind);
+    log(
+        LogLevel::Info,
+        &format!("Retry {} for '{}' no longer waits.", job_id, job.entry.id),
+    );
+    app_state.queue_notify.notify_one();
+}
+
+/// The hold that disables certificate `id`: one set through the API, or
+/// `disabled` in its config entry.
+pub fn disabled_hold(app_state: &AppState, id: &str) -> Option<Hold> {
+    let now = Utc::now();
+    if let Some(hold) = app_state.holds.read().disabled(id, now) {
+        return Some(hold.clone());
+    }
+    app_state
+        .certificates
+        .read()
+        .get(id)
+        .and_then(|entry| entry.disabled.clone())
+        .filter(|hold| hold.is_active(now))
+}
+
+/// Why no job for certificate `id` may start now, and when that ends if
+/// it is known.
+fn held_back(app_state: &AppState, id: &str) -> Option<(String, Option<DateTime<Utc>>)> {
+    if let Some(hold) = app_state.holds.read().paused(Utc::now()) {
+        return Some((hold.describe("scheduler is paused"), hold.until));
+    }
+    disabled_hold(app_state, id).map(|hold| (hold.describe("certificate is disabled"), hold.until))
+}
+
+fn mark_queued(app_state: &AppState, id: &str, kind: JobKind) {
+    if kind == JobKind::Acquire {
+        let mut domains = app_state.domains.write();
+        if !matches!(domains.get(id), Some(DomainStatus::Ready)) {
+            domains.set(id, DomainStatus::Queued);
+        }
+    }
+}
+
+/// Puts back the jobs that were still queued or running when the daemon
+/// last stopped.
+pub async fn restore_queue(app_state: &AppState) {
+    let persisted = queue::load(&app_state.config.dir_path).await;
+    if persisted.is_empty() {
+        return;
+    }
+    let restored = {
+        let mut jobs = app_state.jobs.write();
+        app_state.queue.write().restore(
+            &mut jobs,
+            persisted,
+            app_state.config.allow_ip_certificates,
+        )
+    };
+    for job in &restored {
+        app_state
+            .certificates
+            .write()
+            .insert(job.entry.id.clone(), job.entry.clone());
+        mark_queued(app_state, &job.entry.id, job.kind);
+    }
+    log(
+        LogLevel::Info,
+        &format!("Restored {} queued job(s).", restored.len()),
+    );
+}
+
+/// Starts queued jobs, most urgent first, as far as the concurrency caps
+/// and CA rate limits allow.
+pub fn spawn_queue_worker(app_state: AppState) {
     tokio::spawn(async move {
-        log(LogLevel::Info, "Starting initial certificate check...");
-        let config = app_state.config.clone();
-        let domain_config_path = config.dir_path.join("config.toml");
+        loop {
+            match start_next_job(&app_state).await {
+                Ok(()) => continue,
+                Err(Some(retry_at)) => {
+                    let wait = (retry_at - Utc::now()).to_std().unwrap_or_default();
+                    let _ = time::timeout(wait, app_state.queue_notify.notified()).await;
+                }
+                Err(None) => app_state.queue_notify.notified().await,
+            }
+        }
+    });
+}
 
-        let domain_config = match config::load_domain_config(&domain_config_path).await {
-            Ok(c) => c,
-            Err(e) => {
+/// Starts the most urgent job that fits the budget. Otherwise returns when
+/// the earliest rate limit clears, or `None` if only a finishing job can help.
+async fn start_next_job(app_state: &AppState) -> Result<(), Option<DateTime<Utc>>> {
+    let pending: Vec<QueuedJob> = {
+        let queue = app_state.queue.read();
+        if queue.running().len() >= app_state.config.max_concurrent_jobs {
+            return Err(None);
+        }
+        queue.pending().into_iter().cloned().collect()
+    };
+
+    let mut retry_at: Option<DateTime<Utc>> = None;
+    for job in pending {
+        if let Some(not_before) = job.not_before.filter(|at| *at > Utc::now()) {
+            retry_at = Some(retry_at.map_or(not_before, |r| r.min(not_before)));
+            let reason = job
+                .not_before_reason
+                .clone()
+                .unwrap_or_else(|| "held back".to_string());
+            app_state
+                .jobs
+                .write()
+                .delay(&job.job_id, reason, Some(not_before));
+            continue;
+        }
+        if let Some((reason, until)) = held_back(app_state, &job.entry.id) {
+            if let Some(until) = until {
+                retry_at = Some(retry_at.map_or(until, |r| r.min(until)));
+            }
+            if app_state
+                .jobs
+                .write()
+                .delay(&job.job_id, reason.clone(), until)
+            {
                 log(
-                    LogLevel::Error,
-                    &format!("Failed to load domain config on startup: {}", e),
+                    LogLevel::Info,
+                    &format!(
+                        "Job {} for '{}' is waiting: {}.",
+                        job.job_id, job.entry.id, reason
+                    ),
                 );
-                return;
+            }
+            continue;
+        }
+        let profile = JobProfile::load(&app_state.config, &job.entry).await;
+        let verdict = {
+            // Taking the job off the queue and marking it running happen
+            // under the jobs lock, so a cancel request sees one or the other.
+            let mut jobs = app_state.jobs.write();
+            let mut budget = app_state.budget.write();
+            match budget.check(&profile, Utc::now()) {
+                Ok(()) => {
+                    // The job may have been cancelled while its profile loaded.
+                    let Some(job) = app_state.queue.write().take(&job.job_id) else {
+                        continue;
+                    };
+                    jobs.start(&job.job_id);
+                    budget.start(&profile);
+                    Ok(job)
+                }
+                Err(blocked) => Err(blocked),
+            }
+        };
+        let (reason, until) = match verdict {
+            Ok(job) => {
+                run_job(app_state.clone(), job, profile);
+                return Ok(());
+            }
+            Err(Blocked::Busy(reason)) => (reason, None),
+            Err(Blocked::RateLimited(reason, at)) => {
+                retry_at = Some(retry_at.map_or(at, |r| r.min(at)));
+                (reason, Some(at))
             }
         };
+        if app_state
+            .jobs
+            .write()
+            .delay(&job.job_id, reason.clone(), until)
+        {
+            log(
+                LogLevel::Info,
+                &format!(
+                    "Job {} for '{}' is waiting: {}.",
+                    job.job_id, job.entry.id, reason
+                ),
+            );
+        }
+    }
+    Err(retry_at)
+}
+
+/// A started job's budget slots and place among the running jobs. Dropping
+/// it frees both, so a job task that panics cannot hold them forever.
+struct RunningJob {
+    app_state: AppState,
+    job_id: String,
+    profile: JobProfile,
+    released: bool,
+}
 
-        for domain in &domain_config.domains {
-            let domain_name = domain.name.trim().to_string();
-            if acme::certificate_exists(&domain_name, &config).await {
+impl RunningJob {
+    /// Frees the job's slots and returns its final status. A job that never
+    /// recorded one is marked failed.
+    fn release(&mut self) -> Option<JobStatus> {
+        self.released = true;
+        let status = {
+            let mut jobs = self.app_state.jobs.write();
+            if jobs
+                .get(&self.job_id)
+                .is_some_and(|record| record.status == JobStatus::Running)
+            {
+                jobs.finish(
+                    &self.job_id,
+                    JobStatus::Failed,
+                    Some("Job stopped unexpectedly".to_string()),
+                );
+            }
+            let record = jobs.get(&self.job_id);
+            self.app_state.budget.write().finish(&self.profile, record);
+            record.map(|j| j.status)
+        };
+        self.app_state.queue.write().finish(&self.job_id);
+        status
+    }
+}
+
+impl Drop for RunningJob {
+    fn drop(&mut self) {
+        if !self.released {
+            self.release();
+            self.app_state.queue_notify.notify_one();
+        }
+    }
+}
+
+fn run_job(app_state: AppState, job: QueuedJob, profile: JobProfile) {
+    let mut running = RunningJob {
+        app_state: app_state.clone(),
+        job_id: job.job_id.clone(),
+        profile,
+        released: false,
+    };
+    tokio::spawn(async move {
+        let command_type = match job.kind {
+            JobKind::Acquire => CommandType::Run,
+            JobKind::Renew => CommandType::Renew,
+        };
+        let next_retry_at = acme::acquire_or_renew_certificate(
+            app_state.clone(),
+            job.entry.clone(),
+            job.persist,
+            command_type,
+            job.job_id.clone(),
+            job.attempt,
+        )
+        .await;
+        let status = running.release();
+        if status == Some(JobStatus::Succeeded) {
+            reschedule_renewal(&app_state, &job).await;
+        }
+        record_history(&app_state, &job, status == Some(JobStatus::Succeeded)).await;
+        if let Some(at) = next_retry_at {
+            let mut reason = format!("attempt {} waits for its retry backoff", job.attempt + 1);
+            let mut at = at;
+            if let Some(open) = window_opens(&app_state.config, &job, at) {
+                at = open;
+                reason = format!(
+                    "attempt {} waits for its maintenance window",
+                    job.attempt + 1
+                );
+            }
+            let retry_id = {
+                let mut jobs = app_state.jobs.write();
                 app_state
-                    .domains
+                    .queue
                     .write()
-                    .insert(domain_name, DomainStatus::Ready);
+                    .retry(&mut jobs, job.clone(), at, reason)
+            };
+            if let Some(retry_id) = retry_id {
+                log(
+                    LogLevel::Info,
+                    &format!("Queued retry {} for '{}'.", retry_id, job.entry.id),
+                );
             }
         }
+        app_state.queue_notify.notify_one();
+    });
+}
 
-        let mut all_successful = true;
-        let domains_to_check = domain_config.domains.clone();
-        for domain in domains_to_check {
-            let domain_name = domain.name.trim();
-            if !acme::certificate_exists(domain_name, &config).await {
-                acme::acquire_or_renew_certificate(
-                    app_state.clone(),
-                    domain.name.clone(),
-                    domain.dns_provider.clone(),
-                    false,
-                    CommandType::Run,
-                )
-                .await;
-                if let Some(DomainStatus::Failed(_)) = app_state.domains.read().get(domain_name) {
-                    all_successful = false;
-                }
-            }
+/// Schedules the next renewal from the certificate a successful job left.
+/// lego may keep a certificate it thinks is early, e.g. when `renew_args`
+/// disagree with `renew_before`; that one waits for the next renewal check
+/// instead of being renewed again right away.
+async fn reschedule_renewal(app_state: &AppState, job: &QueuedJob) {
+    let renewal = match acme::scheduled_renewal(&job.entry, &app_state.config).await {
+        Ok(renewal) => renewal,
+        Err(e) => {
+            log(
+                LogLevel::Error,
+                &format!("Error scheduling renewal for '{}': {}", job.entry.id, e),
+            );
+            return;
         }
+    };
+    let now = Utc::now();
+    if renewal.renew_at <= now {
+        let retry_at = next_check_at(&app_state.config, now);
+        log(
+            LogLevel::Warn,
+            &format!(
+                "Certificate for '{}' is still due for renewal after job {}; trying again at {}.",
+                job.entry.id, job.job_id, retry_at
+            ),
+        );
+        app_state
+            .renewals
+            .write()
+            .postpone(&job.entry.id, renewal, retry_at);
+    } else {
+        app_state.renewals.write().set(&job.entry.id, renewal);
+    }
+    app_state.renewal_notify.notify_one();
+}
 
-        log(LogLevel::Info, "Initial certificate check complete.");
+/// Appends the finished run to the history, with the fingerprint of the
+/// certificate it issued.
+async fn record_history(app_state: &AppState, job: &QueuedJob, issued: bool) {
+    let mut fingerprint = None;
+    let resource = app_state.metadata.read().get(&job.entry.id).cloned();
+    if issued && let Some(resource) = resource {
+        let cert_dir = app_state.config.dir_path.join(".lego/certificates");
+        if let Ok(data) = tokio::fs::read(resource.path(&cert_dir, "crt")).await {
+            fingerprint = history::fingerprint(&data);
+        }
+    }
+    let Some(entry) = app_state
+        .jobs
+        .read()
+        .get(&job.job_id)
+        .map(|record| HistoryEntry::new(record, job.attempt, fingerprint))
+    else {
+        return;
+    };
+    app_state.history.append(entry).await;
+}
 
-        if all_successful {
+/// When a renewal that could start at `at` may actually start, if that is
+/// later: the next opening of its maintenance window, but no later than
+/// `critical_expiry_days` before expiry. Renewals already that close to
+/// expiry never wait.
+fn window_opens(config: &AppConfig, job: &QueuedJob, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if job.kind != JobKind::Renew {
+        return None;
+    }
+    let window = job.entry.maintenance_window.as_ref()?;
+    let critical = job
+        .deadline
+        .map(|expiry| expiry - chrono::Duration::days(config.critical_expiry_days));
+    let open = window.next_open(at)?;
+    let open = critical.map_or(open, |critical| open.min(critical));
+    (open > at).then_some(open)
+}
+
+/// Holds a freshly queued renewal until its maintenance window opens.
+fn hold_for_window(app_state: &AppState, job_id: &str) {
+    let mut queue = app_state.queue.write();
+    let Some(job) = queue
+        .pending()
+        .into_iter()
+        .find(|j| j.job_id == job_id)
+        .cloned()
+    else {
+        return;
+    };
+    if let Some(open) = window_opens(&app_state.config, &job, Utc::now()) {
+        log(
+            LogLevel::Info,
+            &format!(
+                "Renewal of '{}' waits for its maintenance window at {}.",
+                job.entry.id, open
+            ),
+        );
+        queue.hold(job_id, open, "waits for its maintenance window".to_string());
+    }
+}
+
+/// The time of the next renewal check: the next cron match, or one interval
+/// from now, plus jitter.
+fn next_check_at(config: &AppConfig, now: DateTime<Utc>) -> DateTime<Utc> {
+    let next = config
+        .renewal_schedule
+        .as_ref()
+        .and_then(|schedule| schedule.next_after(now))
+        .unwrap_or_else(|| {
+            now + chrono::Duration::from_std(config.update_interval).unwrap_or_default()
+        });
+    let jitter = config.renewal_jitter.mul_f64(retry::unit_random());
+    next + chrono::Duration::from_std(jitter).unwrap_or_default()
+}
+
+/// Reads `config.toml` at startup: applies its pause, takes over its
+/// certificate entries (including those of restored jobs) and matches their
+/// statuses to the files on disk. Runs before the queue worker starts, so
+/// holds in the config apply to restored jobs too.
+pub async fn load_startup_config(app_state: &AppState) -> Option<Vec<CertificateEntry>> {
+    log(LogLevel::Info, "Starting initial certificate check...");
+    let config = app_state.config.clone();
+    let domain_config_path = config.dir_path.join("config.toml");
+
+    let domain_config = match config::load_domain_config(&domain_config_path).await {
+        Ok(c) => c,
+        Err(e) => {
             log(
-                LogLevel::Info,
-                "All startup checks passed. Activating periodic renewal task.",
+                LogLevel::Error,
+                &format!("Failed to load domain config on startup: {}", e),
             );
-            *app_state.task_running.write() = true;
-            spawn_periodic_renewal_task(app_state);
+            return None;
+        }
+    };
+
+    app_state
+        .holds
+        .write()
+        .set_config_pause(domain_config.pause.clone());
+    let entries =
+        routable_entries(&config, domain_config.entries(config.allow_ip_certificates)).await;
+    for entry in &entries {
+        app_state
+            .certificates
+            .write()
+            .insert(entry.id.clone(), entry.clone());
+        // A failure from the last run stays until the certificate is
+        // tried again; a status that no longer matches the files does not.
+        let exists = acme::certificate_exists(app_state, entry).await;
+        let mut domains = app_state.domains.write();
+        match (domains.get(&entry.id), exists) {
+            (Some(DomainStatus::Failed { .. }), _) | (Some(DomainStatus::Ready), true) => {}
+            (Some(DomainStatus::Ready), false) => domains.remove(&entry.id),
+            (_, true) => domains.set(&entry.id, DomainStatus::Ready),
+            (_, false) => {}
+        }
+    }
+    Some(entries)
+}
+
+/// Drops entries that route SANs through providers lego cannot mix, the
+/// same way `DomainConfig::entries` drops other invalid entries.
+async fn routable_entries(
+    config: &AppConfig,
+    entries: Vec<CertificateEntry>,
+) -> Vec<CertificateEntry> {
+    let mut routable = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match lego::check_routing(&entry, config).await {
+            Ok(()) => routable.push(entry),
+            Err(e) => log(
+                LogLevel::Error,
+                &format!(
+                    "Skipping invalid entry in config.toml: certificate '{}': {}",
+                    entry.id, e
+                ),
+            ),
+        }
+    }
+    routable
+}
+
+/// Queues the configured certificates that are missing, then starts the
+/// renewal scheduler.
+pub fn spawn_startup_check_task(app_state: AppState, entries: Option<Vec<CertificateEntry>>) {
+    tokio::spawn(async move {
+        // Renewal runs no matter how the initial check goes; its health is
+        // tracked per domain instead.
+        *app_state.task_running.write() = true;
+
+        let Some(entries) = entries else {
+            spawn_renewal_scheduler(app_state.clone());
+            return;
+        };
+
+        let mut job_ids = Vec::new();
+        for entry in entries {
+            if !app_state.metadata.read().contains_key(&entry.id) {
+                let enqueued = enqueue(
+                    &app_state,
+                    entry,
+                    JobKind::Acquire,
+                    Trigger::Startup,
+                    false,
+                    None,
+                );
+                job_ids.push(enqueued.job_id().to_string());
+            }
+        }
+        // Started only now, so that its first rescan finds the startup jobs queued.
+        spawn_renewal_scheduler(app_state.clone());
+
+        let mut failed = 0;
+        for job_id in &job_ids {
+            if jobs::wait_finished(&app_state.jobs, job_id).await != Some(JobStatus::Succeeded) {
+                failed += 1;
+            }
+        }
+
+        if failed == 0 {
+            log(LogLevel::Info, "Initial certificate check complete.");
         } else {
             log(
                 LogLevel::Warn,
-                "One or more startup certificate checks failed. Periodic renewal task will NOT be activated.",
+                &format!(
+                    "Initial certificate check complete: {} of {} acquisition(s) failed. See /v1/task for details.",
+                    failed,
+                    job_ids.len()
+                ),
             );
         }
     });
 }
 
-fn spawn_periodic_renewal_task(app_state: AppState) {
+/// Renews each certificate when it comes due. Due times are kept in
+/// `renewals` and recomputed on every issuance, when `config.toml` changes
+/// and on each full rescan of the configured certificates.
+fn spawn_renewal_scheduler(app_state: AppState) {
     tokio::spawn(async move {
-        log(
-            LogLevel::Info,
-            &format!(
-                "Certificate renewal task scheduled to run every {:?}",
-                app_state.config.update_interval
+        match &app_state.config.renewal_schedule {
+            Some(_) => log(
+                LogLevel::Info,
+                "Certificate renewal scheduler started; full rescans follow RENEWAL_SCHEDULE",
             ),
-        );
-        let mut interval = time::interval(app_state.config.update_interval);
-        interval.tick().await;
-
-        loop {
-            interval.tick().await;
-            log(
+            None => log(
                 LogLevel::Info,
-                "Running scheduled certificate renewal check...",
-            );
+                &format!(
+                    "Certificate renewal scheduler started; full rescans every {:?}",
+                    app_state.config.update_interval
+                ),
+            ),
+        }
 
-            let config_path = app_state.config.dir_path.join("config.toml");
-            let domain_config = match config::load_domain_config(&config_path).await {
-                Ok(c) => c,
-                Err(e) => {
+        let config_path = app_state.config.dir_path.join("config.toml");
+        let mut config_modified = None;
+        let mut next_rescan = Utc::now();
+        loop {
+            let now = Utc::now();
+            let modified = modified_time(&config_path).await;
+            if now >= next_rescan || modified != config_modified {
+                if now < next_rescan {
                     log(
-                        LogLevel::Error,
-                        &format!("Renewal task: Failed to load config.toml: {}", e),
+                        LogLevel::Info,
+                        "config.toml changed; recomputing renewal times...",
                     );
-                    continue;
-                }
-            };
-
-            for domain_entry in domain_config.domains {
-                let domain_name = domain_entry.name.trim();
-
-                if *app_state.is_acquiring.read() {
+                } else {
+                    log(LogLevel::Info, "Rescanning configured certificates...");
+                    next_rescan = next_check_at(&app_state.config, now);
+                    *app_state.next_renewal_check.write() = Some(next_rescan);
                     log(
-                        LogLevel::Warn,
-                        "Another task is already running, postponing renewal check cycle.",
+                        LogLevel::Debug,
+                        &format!("Next full rescan at {}", next_rescan),
                     );
-                    break;
                 }
+                config_modified = modified;
+                rescan(&app_state, &config_path).await;
+            }
 
-                match acme::needs_renewal(domain_name, &app_state.config, 30).await {
-                    Ok(true) => {
-                        log(
-                            LogLevel::Warn,
-                            &format!("Proceeding with renewal for '{}'...", domain_name),
-                        );
-
-                        *app_state.is_acquiring.write() = true;
-
-                        acme::acquire_or_renew_certificate(
-                            app_state.clone(),
-                            domain_name.to_string(),
-                            domain_entry.dns_provider,
-                            false,
-                            CommandType::Renew,
-                        )
-                        .await;
-                    }
-                    Ok(false) => {}
-                    Err(e) => {
-                        log(
-                            LogLevel::Error,
-                            &format!("Error checking renewal status for '{}': {}", domain_name, e),
-                        );
-                    }
-                }
+            queue_due_renewals(&app_state, Utc::now());
+
+            let now = Utc::now();
+            let mut wake = next_rescan.min(now + CONFIG_POLL_INTERVAL);
+            if let Some(due) = app_state.renewals.read().next_due() {
+                wake = wake.min(due);
+            }
+            tokio::select! {
+                _ = time::sleep((wake - now).to_std().unwrap_or_default()) => {}
+                _ = app_state.renewal_notify.notified() => {}
             }
         }
     });
 }
+
+async fn modified_time(path: &std::path::Path) -> Option<std::time::SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// Registers every configured certificate, queues the missing ones and
+/// recomputes the renewal time of the issued ones.
+async fn rescan(app_state: &AppState, config_path: &std::path::Path) {
+    let domain_config = match config::load_domain_config(config_path).await {
+        Ok(c) => c,
+        Err(e) => {
+            log(
+                LogLevel::Error,
+                &format!("Renewal scheduler: Failed to load config.toml: {}", e),
+            );
+            return;
+        }
+    };
+
+    app_state
+        .holds
+        .write()
+        .set_config_pause(domain_config.pause.clone());
+    let entries = routable_entries(
+        &app_state.config,
+        domain_config.entries(app_state.config.allow_ip_certificates),
+    )
+    .await;
+    app_state
+        .renewals
+        .write()
+        .retain(|id| entries.iter().any(|e| e.id == id));
+
+    for entry in entries {
+        app_state
+            .certificates
+            .write()
+            .insert(entry.id.clone(), entry.clone());
+
+        if !acme::certificate_exists(app_state, &entry).await {
+            app_state.renewals.write().remove(&entry.id);
+            // Missing certificates are acquired again, unless their
+            // retries are used up.
+            let exhausted = matches!(
+                app_state.domains.read().get(&entry.id),
+                Some(DomainStatus::Failed {
+                    next_retry_at: None,
+                    ..
+                })
+            );
+            if !exhausted {
+                enqueue(
+                    app_state,
+                    entry,
+                    JobKind::Acquire,
+                    Trigger::Schedule,
+                    false,
+                    None,
+                );
+            }
+            continue;
+        }
+
+        {
+            let mut domains = app_state.domains.write();
+            if domains.get(&entry.id).is_none() {
+                domains.set(&entry.id, DomainStatus::Ready);
+            }
+        }
+        if let Err(e) = schedule_renewal(app_state, &entry).await {
+            log(
+                LogLevel::Error,
+                &format!("Error checking renewal status for '{}': {}", entry.id, e),
+            );
+        }
+    }
+    // A pause or disabled flag in config.toml may have been lifted.
+    app_state.queue_notify.notify_one();
+}
+
+/// Reads the issued certificate for `entry` and schedules its renewal.
+async fn schedule_renewal(
+    app_state: &AppState,
+    entry: &CertificateEntry,
+) -> Result<ScheduledRenewal, Box<dyn std::error::Error + Send + Sync>> {
+    let renewal = acme::scheduled_renewal(entry, &app_state.config).await?;
+    log(
+        LogLevel::Debug,
+        &format!(
+            "Certificate for '{}' is valid until {}; renewal due at {}.",
+            entry.id, renewal.expires_at, renewal.renew_at
+        ),
+    );
+    app_state.renewals.write().set(&entry.id, renewal);
+    app_state.renewal_notify.notify_one();
+    Ok(renewal)
+}
+
+/// Queues a renewal for every certificate whose renewal time has come.
+fn queue_due_renewals(app_state: &AppState, now: DateTime<Utc>) {
+    let due = app_state.renewals.write().take_due(now);
+    for (id, renewal) in due {
+        let Some(entry) = app_state.certificates.read().get(&id).cloned() else {
+            continue;
+        };
+        log(
+            LogLevel::Warn,
+            &format!(
+                "Certificate for '{}' expires on {}. Queueing renewal...",
+                id, renewal.expires_at
+            ),
+        );
+        let enqueued = enqueue(
+            app_state,
+            entry,
+            JobKind::Renew,
+            Trigger::Schedule,
+            false,
+            Some(renewal.expires_at),
+        );
+        if let Enqueued::New(job_id) = enqueued {
+            hold_for_window(app_state, &job_id);
+        }
+    }
+}
+
+/// Prints the plan for every configured certificate (or only `ids`), as the
+/// startup check would run it. Returns whether every plan could be built.
+pub async fn print_dry_run(config: &AppConfig, ids: &[&String]) -> bool {
+    let domain_config_path = config.dir_path.join("config.toml");
+    let domain_config = match config::load_domain_config(&domain_config_path).await {
+        Ok(c) => c,
+        Err(e) => {
+            log(
+                LogLevel::Error,
+                &format!("Failed to load domain config: {}", e),
+            );
+            return false;
+        }
+    };
+
+    let mut all_ok = true;
+    for entry in domain_config.entries(config.allow_ip_certificates) {
+        if !ids.is_empty() && !ids.iter().any(|id| **id == entry.id) {
+            continue;
+        }
+        match acme::plan(config, &entry).await {
+            Ok(plan) => match serde_json::to_string_pretty(&plan) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    log(LogLevel::Error, &e.to_string());
+                    all_ok = false;
+                }
+            },
+            Err(e) => {
+                log(
+                    LogLevel::Error,
+                    &format!("Dry run for '{}' failed: {}", entry.id, e),
+                );
+                all_ok = false;
+            }
+        }
+    }
+    all_ok
+}
@@ -0,0 +1,367 @@
+/* src/template.rs */
+
+//! Provider templates:
+//!
+//! - `{{NAME}}` is required: rendering fails when it has no value.
+//! - `{{NAME|default}}` falls back to `default`.
+//! - `{{#NAME}}..{{/NAME}}` is kept only when `NAME` has a value,
+//!   `{{^NAME}}..{{/NAME}}` only when it does not.
+//!
+//! Names are matched case-insensitively. An empty value counts as unset.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Placeholders every certificate-side template can use.
+pub const CERT_BUILTINS: [&str; 7] = [
+    "DOMAIN",
+    "ID",
+    "SANS",
+    "ZONE",
+    "RECORD_NAME",
+    "KEY_TYPE",
+    "RENEW_DAYS",
+];
+/// Placeholders webhook templates can use.
+pub const HOOK_BUILTINS: [&str; 6] = ["ACTION", "FQDN", "VALUE", "ZONE", "RECORD_NAME", "TTL"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    Syntax(String),
+    Missing(Vec<String>),
+    Unknown(Vec<String>),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Syntax(message) => write!(f, "invalid template: {}", message),
+            TemplateError::Missing(names) => {
+                write!(f, "no value for required {}", names.join(", "))
+            }
+            TemplateError::Unknown(names) => write!(
+                f,
+                "unknown {}; define it or give a default with {{{{NAME|default}}}}",
+                names.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+enum Node {
+    Text(String),
+    Var {
+        name: String,
+        default: Option<String>,
+    },
+    Section {
+        name: String,
+        inverted: bool,
+        body: Vec<Node>,
+    },
+}
+
+fn parse(template: &str) -> Result<Vec<Node>, TemplateError> {
+    // Each open section keeps its name, kind and the nodes collected so far.
+    let mut stack: Vec<(String, bool, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| TemplateError::Syntax("unclosed '{{'".to_string()))?;
+        let tag = after[..end].trim();
+        rest = &after[end + 2..];
+
+        if let Some(name) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+            let name = check_name(name.trim())?;
+            let outer = std::mem::take(&mut nodes);
+            stack.push((name, tag.starts_with('^'), outer));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            let name = check_name(name.trim())?;
+            let (open, inverted, outer) = stack.pop().ok_or_else(|| {
+                TemplateError::Syntax(format!("'{{{{/{}}}}}' closes nothing", name))
+            })?;
+            if open != name {
+                return Err(TemplateError::Syntax(format!(
+                    "'{{{{/{}}}}}' closes section '{}'",
+                    name, open
+                )));
+            }
+            let body = std::mem::replace(&mut nodes, outer);
+            nodes.push(Node::Section {
+                name,
+                inverted,
+                body,
+            });
+        } else {
+            let (name, default) = match tag.split_once('|') {
+                Some((name, default)) => (name.trim(), Some(default.to_string())),
+                None => (tag, None),
+            };
+            nodes.push(Node::Var {
+                name: check_name(name)?,
+                default,
+            });
+        }
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+    }
+    if let Some((open, _, _)) = stack.last() {
+        return Err(TemplateError::Syntax(format!(
+            "section '{}' is never closed",
+            open
+        )));
+    }
+    Ok(nodes)
+}
+
+fn check_name(name: &str) -> Result<String, TemplateError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(TemplateError::Syntax(format!(
+            "'{}' is not a valid placeholder name",
+            name
+        )));
+    }
+    Ok(name.to_ascii_uppercase())
+}
+
+fn value_of(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Option<String> {
+    lookup(name).filter(|v| !v.is_empty())
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    lookup: &impl Fn(&str) -> Option<String>,
+    out: &mut String,
+    missing: &mut BTreeSet<String>,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var { name, default } => {
+                match value_of(lookup, name).or_else(|| default.clone()) {
+                    Some(value) => out.push_str(&value),
+                    None => {
+                        missing.insert(name.clone());
+                    }
+                }
+            }
+            Node::Section {
+                name,
+                inverted,
+                body,
+            } => {
+                if value_of(lookup, name).is_some() != *inverted {
+                    render_nodes(body, lookup, out, missing);
+                }
+            }
+        }
+    }
+}
+
+/// Renders `template`, looking placeholder values up with `lookup`.
+/// Fails with every required placeholder that has no value.
+pub fn render(
+    template: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, TemplateError> {
+    let nodes = parse(template)?;
+    let mut out = String::new();
+    let mut missing = BTreeSet::new();
+    render_nodes(&nodes, &lookup, &mut out, &mut missing);
+    if missing.is_empty() {
+        Ok(out)
+    } else {
+        Err(TemplateError::Missing(missing.into_iter().collect()))
+    }
+}
+
+/// Every placeholder name in `template`, uppercased, including section names.
+/// A template that does not parse has none.
+pub fn placeholders(template: &str) -> Vec<String> {
+    fn collect(nodes: &[Node], names: &mut BTreeSet<String>) {
+        for node in nodes {
+            match node {
+                Node::Text(_) => {}
+                Node::Var { name, .. } => {
+                    names.insert(name.clone());
+                }
+                Node::Section { name, body, .. } => {
+                    names.insert(name.clone());
+                    collect(body, names);
+                }
+            }
+        }
+    }
+    let mut names = BTreeSet::new();
+    if let Ok(nodes) = parse(template) {
+        collect(&nodes, &mut names);
+    }
+    names.into_iter().collect()
+}
+
+/// Checks that `template` parses and that every placeholder without a
+/// default is one `is_known` accepts. Section names may be anything.
+pub fn validate(template: &str, is_known: impl Fn(&str) -> bool) -> Result<(), TemplateError> {
+    fn collect(nodes: &[Node], unknown: &mut BTreeSet<String>, is_known: &impl Fn(&str) -> bool) {
+        for node in nodes {
+            match node {
+                Node::Var {
+                    name,
+                    default: None,
+                } if !is_known(name) => {
+                    unknown.insert(name.clone());
+                }
+                Node::Section { body, .. } => collect(body, unknown, is_known),
+                _ => {}
+            }
+        }
+    }
+    let nodes = parse(template)?;
+    let mut unknown = BTreeSet::new();
+    collect(&nodes, &mut unknown, &is_known);
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(TemplateError::Unknown(unknown.into_iter().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let pairs: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        }
+    }
+
+    #[test]
+    fn renders_placeholders_case_insensitively() {
+        let lookup = vars(&[("DOMAIN", "example.com")]);
+        assert_eq!(
+            render("-d {{domain}} -d {{ DOMAIN }}", lookup).unwrap(),
+            "-d example.com -d example.com"
+        );
+    }
+
+    #[test]
+    fn missing_values_fail_instead_of_rendering_empty() {
+        let lookup = vars(&[("A", ""), ("C", "c")]);
+        assert_eq!(
+            render("{{A}}{{B}}{{C}}", lookup),
+            Err(TemplateError::Missing(vec![
+                "A".to_string(),
+                "B".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn defaults_apply_to_unset_and_empty_values() {
+        let lookup = vars(&[("EMPTY", ""), ("SET", "x")]);
+        assert_eq!(
+            render("{{UNSET|a}} {{EMPTY|b}} {{SET|c}} {{UNSET|}}.", lookup).unwrap(),
+            "a b x ."
+        );
+        // Only the first '|' separates the default.
+        assert_eq!(
+            render("{{CA|https://a|b}}", vars(&[])).unwrap(),
+            "https://a|b"
+        );
+    }
+
+    #[test]
+    fn sections_depend_on_whether_a_value_is_set() {
+        let template = "{{#TOKEN}}--token {{TOKEN}}{{/TOKEN}}{{^TOKEN}}--anonymous{{/TOKEN}}";
+        assert_eq!(
+            render(template, vars(&[("TOKEN", "t")])).unwrap(),
+            "--token t"
+        );
+        assert_eq!(render(template, vars(&[])).unwrap(), "--anonymous");
+        assert_eq!(
+            render(template, vars(&[("TOKEN", "")])).unwrap(),
+            "--anonymous"
+        );
+    }
+
+    #[test]
+    fn skipped_sections_do_not_require_their_placeholders() {
+        assert_eq!(render("{{#A}}{{B}}{{/A}}ok", vars(&[])).unwrap(), "ok");
+    }
+
+    #[test]
+    fn sections_nest() {
+        let template = "{{#A}}a{{#B}}b{{/B}}{{^B}}!b{{/B}}{{/A}}";
+        assert_eq!(
+            render(template, vars(&[("A", "1"), ("B", "1")])).unwrap(),
+            "ab"
+        );
+        assert_eq!(render(template, vars(&[("A", "1")])).unwrap(), "a!b");
+        assert_eq!(render(template, vars(&[("B", "1")])).unwrap(), "");
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        for template in [
+            "{{A",
+            "{{#A}}x",
+            "x{{/A}}",
+            "{{#A}}{{#B}}{{/A}}{{/B}}",
+            "{{}}",
+            "{{A-B}}",
+            "{{#}}{{/}}",
+        ] {
+            assert!(
+                matches!(render(template, vars(&[])), Err(TemplateError::Syntax(_))),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn text_without_placeholders_is_unchanged() {
+        assert_eq!(render("a } b { c", vars(&[])).unwrap(), "a } b { c");
+        assert_eq!(render("", vars(&[])).unwrap(), "");
+    }
+
+    #[test]
+    fn placeholders_lists_every_name_once() {
+        assert_eq!(
+            placeholders("{{a}} {{#B}}{{C|x}}{{/B}} {{A}}"),
+            ["A", "B", "C"]
+        );
+        assert!(placeholders("{{#A}}").is_empty());
+    }
+
+    #[test]
+    fn validate_accepts_known_names_and_defaults() {
+        let known = |name: &str| CERT_BUILTINS.contains(&name);
+        assert!(validate("{{DOMAIN}} {{OTHER|x}} {{#ANY}}{{ID}}{{/ANY}}", known).is_ok());
+        assert_eq!(
+            validate("{{DOMAIN}} {{NOPE}} {{#ANY}}{{ALSO}}{{/ANY}}", known),
+            Err(TemplateError::Unknown(vec![
+                "ALSO".to_string(),
+                "NOPE".to_string()
+            ]))
+        );
+    }
+}
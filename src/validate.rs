@@ -0,0 +1,318 @@
+/* src/validate.rs */
+
+use std::fmt;
+use std::net::IpAddr;
+
+const MAX_NAME_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_ID_LENGTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    Empty,
+    PathTraversal(String),
+    IpNotAllowed(String),
+    MisplacedWildcard(String),
+    Idn(String),
+    TooLong(String),
+    TooFewLabels(String),
+    EmptyLabel(String),
+    LabelTooLong(String),
+    InvalidCharacter(String, char),
+    HyphenAtLabelEdge(String),
+    NumericTld(String),
+    InvalidIdentifier(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "name is empty"),
+            NameError::PathTraversal(name) => {
+                write!(f, "'{}' contains a path separator or '..'", name)
+            }
+            NameError::IpNotAllowed(name) => write!(
+                f,
+                "'{}' is an IP address; set ALLOW_IP_CERTIFICATES=true to allow IP certificates",
+                name
+            ),
+            NameError::MisplacedWildcard(name) => write!(
+                f,
+                "'{}' has a wildcard that is not the whole leftmost label",
+                name
+            ),
+            NameError::Idn(name) => write!(f, "'{}' is not a valid internationalized name", name),
+            NameError::TooLong(name) => {
+                write!(
+                    f,
+                    "'{}' is longer than {} characters",
+                    name, MAX_NAME_LENGTH
+                )
+            }
+            NameError::TooFewLabels(name) => {
+                write!(f, "'{}' must have at least two labels", name)
+            }
+            NameError::EmptyLabel(name) => write!(f, "'{}' contains an empty label", name),
+            NameError::LabelTooLong(label) => write!(
+                f,
+                "label '{}' is longer than {} characters",
+                label, MAX_LABEL_LENGTH
+            ),
+            NameError::InvalidCharacter(label, c) => {
+                write!(f, "label '{}' contains invalid character {:?}", label, c)
+            }
+            NameError::HyphenAtLabelEdge(label) => {
+                write!(f, "label '{}' starts or ends with a hyphen", label)
+            }
+            NameError::NumericTld(name) => {
+                write!(f, "'{}' has an all-numeric top-level label", name)
+            }
+            NameError::InvalidIdentifier(id) => write!(
+                f,
+                "'{}' is not a valid identifier (use letters, digits, '.', '_' and '-', up to {} characters)",
+                id, MAX_ID_LENGTH
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// A name that passed validation: `ascii` is what goes into files and lego,
+/// `display` is the Unicode form for humans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidName {
+    pub ascii: String,
+    pub display: String,
+}
+
+/// Validates a certificate name. Wildcards are only allowed as the whole
+/// leftmost label, IDNs are converted to punycode, and IP addresses are
+/// rejected unless `allow_ip` is set.
+pub fn validate_domain(input: &str, allow_ip: bool) -> Result<ValidName, NameError> {
+    let name = input.trim().trim_end_matches('.');
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    // `..` on its own is caught below as an empty label.
+    if has_path_separator(name) {
+        return Err(NameError::PathTraversal(name.to_string()));
+    }
+
+    let unbracketed = name.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        if !allow_ip {
+            return Err(NameError::IpNotAllowed(name.to_string()));
+        }
+        let ip = ip.to_string();
+        return Ok(ValidName {
+            ascii: ip.clone(),
+            display: ip,
+        });
+    }
+
+    let (wildcard, base) = match name.strip_prefix("*.") {
+        Some(base) => (true, base),
+        None => (false, name),
+    };
+    if base.contains('*') {
+        return Err(NameError::MisplacedWildcard(name.to_string()));
+    }
+
+    let ascii_base = if base.is_ascii() {
+        base.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii(base).map_err(|_| NameError::Idn(name.to_string()))?
+    };
+    check_rfc1123(&ascii_base)?;
+
+    let (display_base, _) = idna::domain_to_unicode(&ascii_base);
+    let prefix = if wildcard { "*." } else { "" };
+    let ascii = format!("{}{}", prefix, ascii_base);
+    if ascii.len() > MAX_NAME_LENGTH {
+        return Err(NameError::TooLong(name.to_string()));
+    }
+    Ok(ValidName {
+        ascii,
+        display: format!("{}{}", prefix, display_base),
+    })
+}
+
+fn check_rfc1123(name: &str) -> Result<(), NameError> {
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(NameError::EmptyLabel(name.to_string()));
+    }
+    if labels.len() < 2 {
+        return Err(NameError::TooFewLabels(name.to_string()));
+    }
+    for label in &labels {
+        if label.len() > MAX_LABEL_LENGTH {
+            return Err(NameError::LabelTooLong(label.to_string()));
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-'))
+        {
+            return Err(NameError::InvalidCharacter(label.to_string(), c));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(NameError::HyphenAtLabelEdge(label.to_string()));
+        }
+    }
+    if labels
+        .last()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err(NameError::NumericTld(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Validates a certificate id or provider name. Both end up in file names,
+/// so only a conservative character set is accepted.
+pub fn validate_identifier(input: &str) -> Result<String, NameError> {
+    let id = input.trim();
+    if id.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if has_path_separator(id) || id.contains("..") {
+        return Err(NameError::PathTraversal(id.to_string()));
+    }
+    let valid_chars = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid_chars || id.starts_with('.') || id.len() > MAX_ID_LENGTH {
+        return Err(NameError::InvalidIdentifier(id.to_string()));
+    }
+    Ok(id.to_string())
+}
+
+fn has_path_separator(name: &str) -> bool {
+    name.contains('/') || name.contains('\\') || name.contains('\0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ascii(input: &str) -> String {
+        validate_domain(input, false).unwrap().ascii
+    }
+
+    fn ascii_ip(input: &str) -> String {
+        validate_domain(input, true).unwrap().ascii
+    }
+
+    #[test]
+    fn names_are_lowercased_and_lose_the_trailing_dot() {
+        assert_eq!(ascii(" Example.COM. "), "example.com");
+        assert_eq!(ascii("a-b.sub.example.co.uk"), "a-b.sub.example.co.uk");
+    }
+
+    #[test]
+    fn wildcards_must_be_the_whole_leftmost_label() {
+        assert_eq!(ascii("*.example.com"), "*.example.com");
+        for name in [
+            "a.*.example.com",
+            "*example.com",
+            "*.*.example.com",
+            "a*.example.com",
+        ] {
+            assert_eq!(
+                validate_domain(name, false),
+                Err(NameError::MisplacedWildcard(name.to_string())),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn idns_round_trip_through_punycode() {
+        let name = validate_domain("Bücher.de", false).unwrap();
+        assert_eq!(name.ascii, "xn--bcher-kva.de");
+        assert_eq!(name.display, "bücher.de");
+
+        let name = validate_domain("xn--bcher-kva.de", false).unwrap();
+        assert_eq!(name.display, "bücher.de");
+
+        let name = validate_domain("*.bücher.de", false).unwrap();
+        assert_eq!(name.ascii, "*.xn--bcher-kva.de");
+        assert_eq!(name.display, "*.bücher.de");
+    }
+
+    #[test]
+    fn ip_addresses_need_allow_ip() {
+        assert_eq!(
+            validate_domain("192.0.2.1", false),
+            Err(NameError::IpNotAllowed("192.0.2.1".to_string()))
+        );
+        assert_eq!(ascii_ip("192.0.2.1"), "192.0.2.1");
+        assert_eq!(ascii_ip("[2001:db8::1]"), "2001:db8::1");
+    }
+
+    #[test]
+    fn malformed_names_are_rejected() {
+        let label = "a".repeat(64);
+        let long = vec!["a".repeat(63); 5].join(".");
+        let cases = [
+            ("", NameError::Empty),
+            (" . ", NameError::Empty),
+            ("../etc", NameError::PathTraversal("../etc".to_string())),
+            ("a\\b.com", NameError::PathTraversal("a\\b.com".to_string())),
+            ("a..com", NameError::EmptyLabel("a..com".to_string())),
+            (
+                "localhost",
+                NameError::TooFewLabels("localhost".to_string()),
+            ),
+            ("-a.com", NameError::HyphenAtLabelEdge("-a".to_string())),
+            ("a-.com", NameError::HyphenAtLabelEdge("a-".to_string())),
+            (
+                "a_b.com",
+                NameError::InvalidCharacter("a_b".to_string(), '_'),
+            ),
+            (
+                "example.123",
+                NameError::NumericTld("example.123".to_string()),
+            ),
+        ];
+        for (input, error) in cases {
+            assert_eq!(validate_domain(input, false), Err(error), "{:?}", input);
+        }
+        assert_eq!(
+            validate_domain(&format!("{}.com", label), false),
+            Err(NameError::LabelTooLong(label))
+        );
+        assert_eq!(
+            validate_domain(&long, false),
+            Err(NameError::TooLong(long.clone()))
+        );
+    }
+
+    #[test]
+    fn identifiers_stay_inside_their_directory() {
+        assert_eq!(
+            validate_identifier(" web-1.prod_x ").unwrap(),
+            "web-1.prod_x"
+        );
+        assert_eq!(validate_identifier(""), Err(NameError::Empty));
+        for id in ["a/b", "a..b", "..", "a\\b"] {
+            assert_eq!(
+                validate_identifier(id),
+                Err(NameError::PathTraversal(id.to_string())),
+                "{}",
+                id
+            );
+        }
+        let long = "a".repeat(MAX_ID_LENGTH + 1);
+        for id in [".hidden", "a b", "a*", long.as_str()] {
+            assert_eq!(
+                validate_identifier(id),
+                Err(NameError::InvalidIdentifier(id.to_string())),
+                "{}",
+                id
+            );
+        }
+    }
+}
@@ -0,0 +1,94 @@
+/* src/zone.rs */
+
+use fancy_log::{LogLevel, log};
+use hickory_resolver::{TokioResolver, proto::rr::RecordType};
+use std::sync::OnceLock;
+
+pub const CHALLENGE_LABEL: &str = "_acme-challenge";
+
+/// Built on first use from the system configuration and shared, so lookups
+/// reuse its connections and cache.
+static RESOLVER: OnceLock<TokioResolver> = OnceLock::new();
+
+fn resolver() -> Result<&'static TokioResolver, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(resolver) = RESOLVER.get() {
+        return Ok(resolver);
+    }
+    let resolver = TokioResolver::builder_tokio()?.build()?;
+    Ok(RESOLVER.get_or_init(|| resolver))
+}
+
+/// Finds the DNS zone that contains `name` by walking up its labels and
+/// looking for the first one that owns an SOA record. The public suffix list
+/// keeps the walk from ever returning something like `co.uk`.
+pub async fn find_zone(name: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let name = normalize(name);
+    let registrable = psl::domain_str(&name)
+        .ok_or_else(|| format!("'{}' is not under a known public suffix", name))?
+        .to_string();
+
+    let resolver = resolver()?;
+    let mut candidate = name.as_str();
+    loop {
+        if has_soa(resolver, candidate).await {
+            log(
+                LogLevel::Debug,
+                &format!("Zone for '{}' is '{}'", name, candidate),
+            );
+            return Ok(candidate.to_string());
+        }
+        if candidate == registrable {
+            break;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => break,
+        }
+    }
+
+    log(
+        LogLevel::Warn,
+        &format!(
+            "No SOA record found for '{}', falling back to '{}' as its zone",
+            name, registrable
+        ),
+    );
+    Ok(registrable)
+}
+
+async fn has_soa(resolver: &TokioResolver, name: &str) -> bool {
+    let Ok(lookup) = resolver.lookup(format!("{}.", name), RecordType::SOA).await else {
+        return false;
+    };
+    lookup.answers().iter().any(|record| {
+        record.record_type() == RecordType::SOA && normalize(&record.name.to_ascii()) == name
+    })
+}
+
+/// The challenge record for `domain`, relative to `zone`,
+/// e.g. `_acme-challenge.api.eu` for `*.api.eu.example.co.uk` in `example.co.uk`.
+pub fn challenge_record_name(domain: &str, zone: &str) -> String {
+    let domain = normalize(domain);
+    let fqdn = format!("{}.{}", CHALLENGE_LABEL, domain.trim_start_matches("*."));
+    relative_name(&fqdn, zone)
+}
+
+/// `fqdn` with the `zone` suffix removed, or `@` for the apex itself.
+pub fn relative_name(fqdn: &str, zone: &str) -> String {
+    let fqdn = normalize(fqdn);
+    let zone = normalize(zone);
+    if fqdn == zone {
+        return "@".to_string();
+    }
+    match fqdn.strip_suffix(&format!(".{}", zone)) {
+        Some(relative) => relative.to_string(),
+        None => fqdn,
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim()
+        .trim_end_matches('.')
+        .trim_start_matches("*.")
+        .to_ascii_lowercase()
+}